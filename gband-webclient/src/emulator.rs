@@ -30,7 +30,7 @@ impl Component for Emulator {
 
     fn create(ctx: &Context<Self>) -> Self {
         let props = ctx.props();
-        let emu = gband::Emulator::new(&props.rom, None).unwrap();
+        let emu = new_emulator(&props.rom);
 
         let interval = {
             let link = ctx.link().clone();
@@ -98,11 +98,21 @@ impl Component for Emulator {
 
     fn changed(&mut self, ctx: &Context<Self>) -> bool {
         let props = ctx.props();
-        self.emu = gband::Emulator::new(&props.rom, None).unwrap();
+        self.emu = new_emulator(&props.rom);
         false
     }
 }
 
+/// Cartridge clocks follow the time of the browser
+fn new_emulator(rom: &[u8]) -> gband::Emulator {
+    let options = gband::EmulatorOptions {
+        time_source: Some(Box::new(crate::wall_clock::WallClock)),
+        ..Default::default()
+    };
+
+    gband::Emulator::with_options(rom, None, options).unwrap()
+}
+
 impl Emulator {
     fn tick(&mut self) {
         use wasm_bindgen::{Clamped, JsCast};
@@ -130,11 +140,7 @@ impl Emulator {
             }
         }
 
-        let frame = loop {
-            if let Some(frame) = self.emu.clock() {
-                break frame;
-            }
-        };
+        let frame = self.emu.run_frame();
 
         // Get canvas 2d context
         let context = self
@@ -1,5 +1,6 @@
 mod app;
 mod emulator;
+mod wall_clock;
 
 fn main() {
     yew::start_app::<app::App>();
@@ -0,0 +1,11 @@
+use gband::TimeSource;
+
+/// Follows the time of the browser, so cartridge clocks keep running between visits
+pub struct WallClock;
+
+impl TimeSource for WallClock {
+    fn now(&self, _emulated_seconds: u64) -> u64 {
+        // Seconds since the UNIX epoch
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
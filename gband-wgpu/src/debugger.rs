@@ -1,6 +1,8 @@
 use crate::emulation_thread::{EmulatorInput, EmulatorState};
 use crate::State;
 
+use gband::StopReason;
+
 use std::io::{stdin, stdout, Write};
 
 use structopt::clap::AppSettings;
@@ -66,6 +68,9 @@ pub enum DebuggerInfoOpt {
     #[structopt(visible_alias = "r", no_version)]
     /// Display registers, or a specific register if specified
     Reg { register: Option<String> },
+    #[structopt(visible_alias = "m", no_version)]
+    /// Display the registers of the cartridge mapper
+    Mapper,
 }
 
 fn parse_hex_addr(src: &str) -> Result<u16, std::num::ParseIntError> {
@@ -80,20 +85,12 @@ impl EmulatorState {
                 .paused
                 .store(false, std::sync::atomic::Ordering::Relaxed),
             DebuggerOpt::Step => {
-                let current_pc = self.emulator.cpu().pc;
-                let mut infinite_loop_counter = 0u8;
-                while {
-                    if let Some(step_frame) = self.emulator.clock() {
-                        self.update_frame(step_frame.as_slice());
-                    }
-                    self.emulator.cpu().cycles > 1 || self.emulator.cpu().pc == current_pc
-                } {
-                    // Safeguard against infinite loop never exiting step. Max t-states is 24
-                    infinite_loop_counter += 1;
-                    if infinite_loop_counter == 50 {
-                        println!("Infinite loop detected");
-                        break;
-                    }
+                if self.emulator.step_instruction() == StopReason::Halted {
+                    println!("The CPU is halted");
+                }
+
+                if let Some(step_frame) = self.emulator.take_frame() {
+                    self.update_frame(&step_frame);
                 }
 
                 self.disassemble(None);
@@ -159,6 +156,11 @@ impl EmulatorState {
                     }
                 }
                 DebuggerInfoOpt::Reg { register } => self.print_registers(register),
+                DebuggerInfoOpt::Mapper => {
+                    for (name, value) in self.emulator.mapper_registers() {
+                        println!("{}: {:02x}", name, value);
+                    }
+                }
             },
         }
     }
@@ -170,7 +172,7 @@ impl EmulatorState {
         let search_addr = if let Some(search_addr) = search_addr {
             search_addr as i32
         } else {
-            cpu.pc as i32
+            cpu.instruction_address as i32
         };
 
         let closest_search_addr = disassembly
@@ -181,7 +183,7 @@ impl EmulatorState {
 
         let closest_pc = disassembly
             .iter()
-            .min_by_key(|&(_, x, _)| ((*x as i32) - (cpu.pc as i32)).abs())
+            .min_by_key(|&(_, x, _)| ((*x as i32) - (cpu.instruction_address as i32)).abs())
             .unwrap()
             .1;
 
@@ -0,0 +1,149 @@
+use gband::{Dmg07, DMG07_PLAYERS};
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long the Game Boys have to answer a byte before the adapter moves on without them
+const REPLY_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Delay between checks for new players while nobody is connected
+const IDLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Clock of the adapter, in Hz
+const CLOCK_RATE: u64 = 4_194_304;
+
+struct Port {
+    socket: TcpStream,
+    /// Whether the Game Boy didn't answer the last byte yet
+    waiting: bool,
+}
+
+/// Run a DMG-07 four player adapter in the background.
+/// Emulators connect to it like to a serial server, and get the first free port.
+pub fn start(address: SocketAddr) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    log::info!("Started the four player adapter on {address}");
+
+    Ok(std::thread::spawn(move || run(listener)))
+}
+
+fn run(listener: TcpListener) {
+    let mut hub = Dmg07::new();
+    let mut ports: [Option<Port>; DMG07_PLAYERS] = Default::default();
+
+    loop {
+        accept_players(&listener, &mut ports);
+
+        if ports.iter().all(Option::is_none) {
+            std::thread::sleep(IDLE_DELAY);
+            continue;
+        }
+
+        // Clock every Game Boy at the same time
+        let started = Instant::now();
+        let outgoing = hub.outgoing();
+        let mut sent = [false; DMG07_PLAYERS];
+        for (player, port) in ports.iter_mut().enumerate() {
+            if let Some(p) = port {
+                // A Game Boy still busy with the last byte misses this one
+                if !p.waiting {
+                    if let Err(e) = p.socket.write_all(&[outgoing[player]]) {
+                        log::warn!("Player {} disconnected: {e}", player + 1);
+                        *port = None;
+                        continue;
+                    }
+
+                    p.waiting = true;
+                    sent[player] = true;
+                }
+            }
+        }
+
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut received = [None; DMG07_PLAYERS];
+        for (player, port) in ports.iter_mut().enumerate() {
+            if let Some(p) = port {
+                let timeout = deadline
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_millis(1));
+                if let Err(e) = p.socket.set_read_timeout(Some(timeout)) {
+                    log::warn!("Could not set the read timeout! {e}");
+                }
+
+                let mut buf = [0u8];
+                match p.socket.read(&mut buf) {
+                    Ok(1) => {
+                        p.waiting = false;
+
+                        // A late answer belongs to a byte the adapter already gave up on
+                        if sent[player] {
+                            received[player] = Some(buf[0]);
+                        }
+                    }
+                    Ok(_) => {
+                        log::info!("Player {} disconnected", player + 1);
+                        *port = None;
+                    }
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut => {}
+                    Err(e) => {
+                        log::warn!("Player {} disconnected: {e}", player + 1);
+                        *port = None;
+                    }
+                }
+            }
+        }
+
+        hub.incoming(received);
+
+        // Wait for the next byte, at the speed picked by the first player
+        let next = started + byte_period(hub.rate());
+        if let Some(remaining) = next.checked_duration_since(Instant::now()) {
+            std::thread::sleep(remaining);
+        }
+    }
+}
+
+/// Time the adapter takes to send a byte.
+/// It sends `CLOCK_RATE / (6 * rate + 512)` bits per second.
+fn byte_period(rate: u8) -> Duration {
+    let cycles = 8 * (6 * rate as u64 + 512);
+    Duration::from_nanos(cycles * 1_000_000_000 / CLOCK_RATE)
+}
+
+fn accept_players(listener: &TcpListener, ports: &mut [Option<Port>; DMG07_PLAYERS]) {
+    loop {
+        match listener.accept() {
+            Ok((socket, addr)) => match ports.iter_mut().position(|port| port.is_none()) {
+                Some(player) => {
+                    log::info!("Player {} connected from {addr}", player + 1);
+
+                    // Accepted sockets can inherit the non-blocking mode of the listener
+                    if let Err(e) = socket.set_nonblocking(false) {
+                        log::warn!("Could not make the socket blocking! {e}");
+                    }
+
+                    if let Err(e) = socket.set_nodelay(true) {
+                        log::warn!("Could not disable Nagle's algorithm! {e}");
+                    }
+
+                    ports[player] = Some(Port {
+                        socket,
+                        waiting: false,
+                    });
+                }
+                None => log::warn!("Refused {addr}, all four ports are taken"),
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                log::error!("Socket accept failed: {}", e);
+                break;
+            }
+        }
+    }
+}
@@ -1,14 +1,21 @@
 use crate::debugger::DebuggerOpt;
-use gband::{Emulator, JoypadState};
+use gband::{CompatibilityPalette, Emulator, Frame, JoypadState, StopReason};
 use spin_sleep::LoopHelper;
 use std::{
-    sync::{atomic::AtomicBool, mpsc, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicU32},
+        mpsc, Arc,
+    },
     thread::JoinHandle,
 };
 
 pub enum EmulatorInput {
     Input(JoypadState),
+    Tilt(f32, f32),
     RequestSaveData(mpsc::Sender<Option<Vec<u8>>>),
+    RequestSaveState(mpsc::Sender<Vec<u8>>),
+    LoadSaveState(Vec<u8>),
+    SetCompatibilityPalette(CompatibilityPalette),
     DebuggerInput(DebuggerOpt),
     Stop,
 }
@@ -19,6 +26,9 @@ pub struct EmulatorState {
     pub paused: Arc<AtomicBool>,
     pub breakpoints: Vec<u16>,
 
+    /// Strength of the rumble of the last frame, as f32 bits
+    rumble: Arc<AtomicU32>,
+
     queue: Arc<wgpu::Queue>,
     texture: wgpu::Texture,
 
@@ -50,28 +60,47 @@ impl EmulatorState {
                 self.loop_helper.loop_start();
 
                 // Get a frame from the emulation and write it to the texture
-                let frame = loop {
-                    if self.breakpoints.contains(&self.emulator.cpu().pc) {
-                        println!("Reached breakpoint at {:04x}", self.emulator.cpu().pc);
-                        self.paused
-                            .store(true, std::sync::atomic::Ordering::Relaxed);
-                        continue 'main_loop;
-                    }
-                    if let Some(f) = self.emulator.clock() {
-                        break f;
-                    }
-                };
+                let breakpoints = &self.breakpoints;
+                let reason = self.emulator.run_until(|emulator| {
+                    breakpoints.contains(&emulator.cpu().instruction_address)
+                });
+
+                if reason == StopReason::Predicate {
+                    println!(
+                        "Reached breakpoint at {:04x}",
+                        self.emulator.cpu().instruction_address
+                    );
+                    self.paused
+                        .store(true, std::sync::atomic::Ordering::Relaxed);
+                    continue 'main_loop;
+                }
+
+                let frame = self
+                    .emulator
+                    .take_frame()
+                    .expect("run_until stopped on a frame");
 
-                self.update_frame(frame.as_slice());
+                self.update_frame(&frame);
+                self.rumble.store(
+                    self.emulator.rumble().to_bits(),
+                    std::sync::atomic::Ordering::Relaxed,
+                );
 
                 self.loop_helper.loop_sleep();
             }
         }
     }
 
-    pub fn update_frame(&self, frame: &[u8]) {
-        let emulator_width = gband::FRAME_WIDTH as u32;
-        let emulator_height = gband::FRAME_HEIGHT as u32;
+    pub fn update_frame(&self, frame: &Frame) {
+        let (emulator_width, emulator_height) = self.emulator.screen_size();
+        let (emulator_width, emulator_height) = (emulator_width as u32, emulator_height as u32);
+
+        // The SGB draws its border around the screen
+        let sgb_frame = self.emulator.sgb_frame(frame);
+        let frame = match &sgb_frame {
+            Some(sgb_frame) => sgb_frame.as_slice(),
+            None => frame.as_slice(),
+        };
 
         // Update texture
         let texture_size = wgpu::Extent3d {
@@ -100,12 +129,20 @@ impl EmulatorState {
     fn handle_inputs(&mut self, input: EmulatorInput) -> bool {
         match input {
             EmulatorInput::Input(x) => self.emulator.set_joypad(x),
+            EmulatorInput::Tilt(x, y) => self.emulator.set_tilt(x, y),
             EmulatorInput::RequestSaveData(sender) => {
-                let save = match self.emulator.get_save_data() {
-                    Some(save) => Some(save.to_vec()),
-                    _ => None,
-                };
-                let _ = sender.send(save);
+                let _ = sender.send(self.emulator.get_save_data());
+            }
+            EmulatorInput::RequestSaveState(sender) => {
+                let _ = sender.send(self.emulator.save_state());
+            }
+            EmulatorInput::LoadSaveState(state) => {
+                if let Err(e) = self.emulator.load_state(&state) {
+                    log::warn!("Couldn't load the save state: {e}");
+                }
+            }
+            EmulatorInput::SetCompatibilityPalette(palette) => {
+                self.emulator.set_compatibility_palette(palette)
             }
             EmulatorInput::DebuggerInput(x) => self.handle_debugger_inputs(x),
             EmulatorInput::Stop => {
@@ -122,6 +159,7 @@ pub fn start(
     queue: Arc<wgpu::Queue>,
     texture: wgpu::Texture,
     paused: Arc<AtomicBool>,
+    rumble: Arc<AtomicU32>,
 ) -> (JoinHandle<()>, mpsc::Sender<EmulatorInput>) {
     let (input_sender, input_receiver) = mpsc::channel::<EmulatorInput>();
 
@@ -136,6 +174,7 @@ pub fn start(
 
         paused,
         breakpoints: Vec::new(),
+        rumble,
 
         input_receiver,
         loop_helper,
@@ -1,6 +1,8 @@
 use emulation_thread::EmulatorInput;
 use futures::executor::block_on;
-use gband::{Emulator, JoypadState};
+use gband::{
+    CompatibilityPalette, Emulator, EmulatorOptions, HardwareModel, JoypadState, MapperKind,
+};
 use wgpu::util::DeviceExt;
 
 use strum_macros::EnumString;
@@ -10,7 +12,11 @@ use std::{
     io::{Read, Write},
     net::SocketAddr,
     path::Path,
-    sync::{atomic::AtomicBool, mpsc::Sender, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicU32},
+        mpsc::Sender,
+        Arc,
+    },
     thread::JoinHandle,
 };
 
@@ -32,6 +38,16 @@ struct Opt {
     #[structopt(parse(from_os_str))]
     rom: Option<PathBuf>,
 
+    /// Path to a DMG or CGB boot ROM to run before the game
+    #[structopt(short = "b", long, parse(from_os_str))]
+    boot_rom: Option<PathBuf>,
+
+    /// Console to emulate.
+    /// Possible values: dmg, mgb, sgb, cgb, agb.
+    /// Defaults to the model of the boot ROM, or cgb.
+    #[structopt(short = "m", long)]
+    model: Option<Model>,
+
     /// Starts the game paused. Can be useful for debugging
     #[structopt(short = "p", long)]
     start_paused: bool,
@@ -48,6 +64,24 @@ struct Opt {
     #[structopt(short = "c", long, group = "serial")]
     client: Option<SocketAddr>,
 
+    /// Connect a Game Boy Printer to the serial port.
+    /// Printed pages are saved as PNG next to the ROM.
+    #[structopt(long, group = "serial")]
+    printer: bool,
+
+    /// Host a DMG-07 four player adapter on the specified bind address, and join it as a player.
+    /// The other players connect to it with --client.
+    #[structopt(long, group = "serial")]
+    hub: Option<SocketAddr>,
+
+    /// Open infrared communication as a server on the specified bind address.
+    #[structopt(long, group = "ir")]
+    ir_server: Option<SocketAddr>,
+
+    /// Open infrared communication as a client on the specified address.
+    #[structopt(long, group = "ir")]
+    ir_client: Option<SocketAddr>,
+
     /// Graphics API to use
     /// Possible values: vulkan, opengl, directx11, directx12
     /// Only Vulkan and DirectX12 are well supported.
@@ -62,6 +96,22 @@ struct Opt {
     #[structopt(long)]
     power_adapter: Option<PowerAdapter>,
 
+    /// Offset in seconds added to the time seen by cartridge clocks, to set the in-game clock.
+    /// Negative values are allowed. It isn't kept in the save file, so pass it on every launch.
+    #[structopt(long, default_value = "0", allow_hyphen_values = true)]
+    rtc_offset: i64,
+
+    /// Path to a picture seen by the Game Boy Camera.
+    /// Defaults to a test pattern.
+    #[structopt(long, parse(from_os_str))]
+    camera_image: Option<PathBuf>,
+
+    /// Mapper to use instead of the one from the header, for unlicensed cartridges.
+    /// Possible values: none, mbc1, mbc2, mbc3, mbc5, mbc6, mbc7, mmm01, huc1, huc3, camera, tama5,
+    /// wisdomtree, sachen, m161
+    #[structopt(long)]
+    mapper: Option<Mapper>,
+
     /// Disables gamepad support
     #[structopt(long = "no-gamepad")]
     #[cfg(feature = "gilrs")]
@@ -92,6 +142,72 @@ enum PowerAdapter {
     High,
 }
 
+#[derive(EnumString, Debug)]
+enum Model {
+    #[strum(ascii_case_insensitive)]
+    Dmg,
+
+    #[strum(ascii_case_insensitive)]
+    Mgb,
+
+    #[strum(ascii_case_insensitive)]
+    Sgb,
+
+    #[strum(ascii_case_insensitive)]
+    Cgb,
+
+    #[strum(ascii_case_insensitive)]
+    Agb,
+}
+
+#[derive(EnumString, Debug)]
+enum Mapper {
+    #[strum(serialize = "none", ascii_case_insensitive)]
+    None,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc1,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc2,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc3,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc5,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc6,
+
+    #[strum(ascii_case_insensitive)]
+    Mbc7,
+
+    #[strum(ascii_case_insensitive)]
+    Mmm01,
+
+    #[strum(ascii_case_insensitive)]
+    HuC1,
+
+    #[strum(ascii_case_insensitive)]
+    HuC3,
+
+    #[strum(serialize = "camera", ascii_case_insensitive)]
+    PocketCamera,
+
+    #[strum(ascii_case_insensitive)]
+    Tama5,
+
+    #[strum(ascii_case_insensitive)]
+    WisdomTree,
+
+    #[strum(serialize = "sachen", ascii_case_insensitive)]
+    SachenMmc1,
+
+    #[strum(ascii_case_insensitive)]
+    M161,
+}
+
 impl Into<wgpu::Backends> for GraphicsApi {
     fn into(self) -> wgpu::Backends {
         match self {
@@ -103,6 +219,40 @@ impl Into<wgpu::Backends> for GraphicsApi {
     }
 }
 
+impl Into<HardwareModel> for Model {
+    fn into(self) -> HardwareModel {
+        match self {
+            Model::Dmg => HardwareModel::Dmg,
+            Model::Mgb => HardwareModel::Mgb,
+            Model::Sgb => HardwareModel::Sgb,
+            Model::Cgb => HardwareModel::Cgb,
+            Model::Agb => HardwareModel::Agb,
+        }
+    }
+}
+
+impl Into<MapperKind> for Mapper {
+    fn into(self) -> MapperKind {
+        match self {
+            Mapper::None => MapperKind::NoMapper,
+            Mapper::Mbc1 => MapperKind::Mbc1,
+            Mapper::Mbc2 => MapperKind::Mbc2,
+            Mapper::Mbc3 => MapperKind::Mbc3,
+            Mapper::Mbc5 => MapperKind::Mbc5,
+            Mapper::Mbc6 => MapperKind::Mbc6,
+            Mapper::Mbc7 => MapperKind::Mbc7,
+            Mapper::Mmm01 => MapperKind::Mmm01,
+            Mapper::HuC1 => MapperKind::HuC1,
+            Mapper::HuC3 => MapperKind::HuC3,
+            Mapper::PocketCamera => MapperKind::PocketCamera,
+            Mapper::Tama5 => MapperKind::Tama5,
+            Mapper::WisdomTree => MapperKind::WisdomTree,
+            Mapper::SachenMmc1 => MapperKind::SachenMmc1,
+            Mapper::M161 => MapperKind::M161,
+        }
+    }
+}
+
 impl Into<wgpu::PowerPreference> for PowerAdapter {
     fn into(self) -> wgpu::PowerPreference {
         match self {
@@ -113,8 +263,11 @@ impl Into<wgpu::PowerPreference> for PowerAdapter {
 }
 
 mod debugger;
+mod dmg07_hub;
 mod emulation_thread;
+mod socket_ir_transport;
 mod socket_serial_transport;
+mod wall_clock;
 
 // This maps the keyboard input to a controller input
 fn winit_to_gband_input(keycode: &VirtualKeyCode) -> Result<JoypadState, ()> {
@@ -227,10 +380,20 @@ impl Vertex {
 struct State {
     emulator_input: Sender<EmulatorInput>,
     joypad: JoypadState,
+    tilt: (f32, f32),
+    compatibility_palette: u8,
 
     #[cfg(feature = "gilrs")]
     gamepad_events: Option<Gilrs>,
 
+    /// Strength of the rumble as f32 bits, written by the emulation thread
+    #[cfg(feature = "gilrs")]
+    rumble: Arc<AtomicU32>,
+
+    /// Force feedback playing on the gamepads, with its magnitude
+    #[cfg(feature = "gilrs")]
+    rumble_effect: Option<(u16, gilrs::ff::Effect)>,
+
     thread_join_handles: Vec<JoinHandle<()>>,
 
     paused: Arc<AtomicBool>,
@@ -308,8 +471,8 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        let emulator_width = gband::FRAME_WIDTH as u32;
-        let emulator_height = gband::FRAME_HEIGHT as u32;
+        let (emulator_width, emulator_height) = emulator.screen_size();
+        let (emulator_width, emulator_height) = (emulator_width as u32, emulator_height as u32);
 
         // Create the texture to show the emulator screen
         let texture_size = wgpu::Extent3d {
@@ -481,8 +644,14 @@ impl State {
         });
 
         let paused = Arc::new(AtomicBool::new(paused));
-        let (join_handle, emulator_input) =
-            emulation_thread::start(emulator, queue.clone(), screen_texture, paused.clone());
+        let rumble = Arc::new(AtomicU32::new(0));
+        let (join_handle, emulator_input) = emulation_thread::start(
+            emulator,
+            queue.clone(),
+            screen_texture,
+            paused.clone(),
+            rumble.clone(),
+        );
 
         let thread_join_handles = vec![join_handle];
 
@@ -490,9 +659,15 @@ impl State {
             emulator_input,
             thread_join_handles,
             joypad: JoypadState::default(),
+            tilt: (0.0, 0.0),
+            compatibility_palette: 0,
 
             #[cfg(feature = "gilrs")]
             gamepad_events,
+            #[cfg(feature = "gilrs")]
+            rumble,
+            #[cfg(feature = "gilrs")]
+            rumble_effect: None,
             paused,
 
             surface,
@@ -554,15 +729,84 @@ impl State {
                 }
                 _ => false,
             },
+            WindowEvent::CursorMoved { position, .. } => {
+                // The mouse tilts the cartridge from the center of the window, for accelerometer games
+                let x = position.x as f32 / self.size.width as f32 * 2.0 - 1.0;
+                let y = position.y as f32 / self.size.height as f32 * 2.0 - 1.0;
+                self.set_tilt(x, y);
+                true
+            }
             _ => false,
         }
     }
 
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+        let _ = self.emulator_input.send(EmulatorInput::Tilt(x, y));
+    }
+
+    /// Forward the rumble of the cartridge to the gamepads with force feedback
+    #[cfg(feature = "gilrs")]
+    fn update_rumble(&mut self) {
+        let gilrs = match &mut self.gamepad_events {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+
+        // Coarse steps, so the effect isn't rebuilt every frame
+        let rumble = f32::from_bits(self.rumble.load(std::sync::atomic::Ordering::Relaxed));
+        let magnitude = ((rumble * 8.0).round() / 8.0 * u16::MAX as f32) as u16;
+
+        let current = self.rumble_effect.as_ref().map(|(m, _)| *m).unwrap_or(0);
+        if magnitude == current {
+            return;
+        }
+
+        // Dropping the effect stops it
+        self.rumble_effect = None;
+        if magnitude == 0 {
+            return;
+        }
+
+        let gamepads: Vec<_> = gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+
+        let effect = gilrs::ff::EffectBuilder::new()
+            .add_effect(gilrs::ff::BaseEffect {
+                kind: gilrs::ff::BaseEffectType::Strong { magnitude },
+                scheduling: gilrs::ff::Replay {
+                    play_for: gilrs::ff::Ticks::from_ms(50),
+                    ..Default::default()
+                },
+                envelope: Default::default(),
+            })
+            .repeat(gilrs::ff::Repeat::Infinitely)
+            .gamepads(&gamepads)
+            .finish(gilrs);
+
+        match effect.and_then(|effect| effect.play().map(|_| effect)) {
+            Ok(effect) => self.rumble_effect = Some((magnitude, effect)),
+            Err(e) => log::warn!("Couldn't start the rumble: {e}"),
+        }
+    }
+
     fn update(&mut self) {
         if self.paused.load(std::sync::atomic::Ordering::Relaxed) {
+            // Stop the rumble while the game is paused
+            #[cfg(feature = "gilrs")]
+            {
+                self.rumble_effect = None;
+            }
+
             // Put the debugger prompt if paused
             self.debugger_prompt()
         } else {
+            #[cfg(feature = "gilrs")]
+            self.update_rumble();
+
             #[cfg(feature = "gilrs")]
             if let Some(gilrs) = &mut self.gamepad_events {
                 if let Some(gilrs::Event {
@@ -571,20 +815,29 @@ impl State {
                     time: _time,
                 }) = gilrs.next_event()
                 {
-                    match gilrs_to_gband_input(event) {
-                        Some(JoypadStateChange::Pressed(input)) => {
-                            self.joypad.insert(input);
-                            self.emulator_input
-                                .send(EmulatorInput::Input(self.joypad))
-                                .expect("Emulation thread crashed");
+                    match event {
+                        // The right stick tilts the cartridge, for accelerometer games
+                        gilrs::EventType::AxisChanged(gilrs::Axis::RightStickX, value, _) => {
+                            self.set_tilt(value, self.tilt.1);
                         }
-                        Some(JoypadStateChange::Released(input)) => {
-                            self.joypad.remove(input);
-                            self.emulator_input
-                                .send(EmulatorInput::Input(self.joypad))
-                                .expect("Emulation thread crashed");
+                        gilrs::EventType::AxisChanged(gilrs::Axis::RightStickY, value, _) => {
+                            self.set_tilt(self.tilt.0, -value);
                         }
-                        None => {}
+                        event => match gilrs_to_gband_input(event) {
+                            Some(JoypadStateChange::Pressed(input)) => {
+                                self.joypad.insert(input);
+                                self.emulator_input
+                                    .send(EmulatorInput::Input(self.joypad))
+                                    .expect("Emulation thread crashed");
+                            }
+                            Some(JoypadStateChange::Released(input)) => {
+                                self.joypad.remove(input);
+                                self.emulator_input
+                                    .send(EmulatorInput::Input(self.joypad))
+                                    .expect("Emulation thread crashed");
+                            }
+                            None => {}
+                        },
                     }
                 }
             }
@@ -663,6 +916,45 @@ impl State {
         }
     }
 
+    fn save_state(&self, state_path: &Path) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let _ = self
+            .emulator_input
+            .send(EmulatorInput::RequestSaveState(sender));
+
+        let state = receiver
+            .recv()
+            .expect("Emulator crashed, couldn't retrieve save state!");
+
+        match std::fs::write(state_path, state) {
+            Ok(_) => log::info!("Saved state to {}", state_path.display()),
+            Err(e) => log::warn!("Couldn't write the save state: {e}"),
+        }
+    }
+
+    fn load_state(&self, state_path: &Path) {
+        match std::fs::read(state_path) {
+            Ok(state) => {
+                let _ = self
+                    .emulator_input
+                    .send(EmulatorInput::LoadSaveState(state));
+            }
+            Err(e) => log::warn!("Couldn't read the save state: {e}"),
+        }
+    }
+
+    /// Cycle through the palettes of the CGB boot ROM for DMG games
+    fn next_compatibility_palette(&mut self) {
+        let palette = CompatibilityPalette::try_from(self.compatibility_palette)
+            .expect("the index is kept in range");
+        self.compatibility_palette = (self.compatibility_palette + 1) % CompatibilityPalette::COUNT;
+
+        log::info!("Switching to the {palette:?} palette");
+        let _ = self
+            .emulator_input
+            .send(EmulatorInput::SetCompatibilityPalette(palette));
+    }
+
     fn pause(&mut self) {
         self.paused
             .store(true, std::sync::atomic::Ordering::Relaxed);
@@ -685,6 +977,22 @@ impl Drop for State {
     }
 }
 
+/// Save a page next to the ROM, numbered after the pages already there
+fn save_printed_page(rom_path: &Path, page: gband::PrintedPage) {
+    let path = (1..)
+        .map(|i| rom_path.with_extension(format!("print{i}.png")))
+        .find(|path| !path.exists())
+        .expect("there is always a free number");
+
+    let image = image::GrayImage::from_raw(page.width as u32, page.height as u32, page.pixels)
+        .expect("the page has the right size");
+
+    match image.save(&path) {
+        Ok(()) => log::info!("Printed page saved to {}", path.display()),
+        Err(e) => log::warn!("Could not save the printed page: {e}"),
+    }
+}
+
 fn main() {
     // Parse CLI options
     let opt = Opt::from_args();
@@ -693,7 +1001,7 @@ fn main() {
         .start()
         .unwrap();
 
-    let icon: &[u8] = if opt.server.is_some() || opt.client.is_some() {
+    let icon: &[u8] = if opt.server.is_some() || opt.client.is_some() || opt.hub.is_some() {
         include_bytes!("../../logos/gband-small-3-transparent.png")
     } else {
         include_bytes!("../../logos/gband-small-1-transparent.png")
@@ -705,18 +1013,6 @@ fn main() {
         winit::window::Icon::from_rgba(icon.to_rgba8().to_vec(), icon.width(), icon.height())
             .expect("invalid icon!");
 
-    // Create the window
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("GBAND")
-        .with_inner_size(winit::dpi::LogicalSize::new(
-            gband::FRAME_WIDTH as f32 * 4.0,
-            gband::FRAME_HEIGHT as f32 * 4.0,
-        ))
-        .with_window_icon(Some(icon))
-        .build(&event_loop)
-        .unwrap();
-
     // Find ROM path
     let path = if let Some(p) = opt.rom {
         p
@@ -731,6 +1027,11 @@ fn main() {
     let mut save_path = path.clone();
     save_path.set_extension("sav");
 
+    let mut state_path = path.clone();
+    state_path.set_extension("state");
+
+    let print_path = path.clone();
+
     // Read the ROM
     let rom = std::fs::read(path).expect("Could not read the ROM file");
 
@@ -743,22 +1044,82 @@ fn main() {
         None
     };
 
+    // Read the boot ROM
+    let boot_rom = opt
+        .boot_rom
+        .map(|path| std::fs::read(path).expect("Could not read the boot ROM file"));
+
     // Create the emulator
-    let mut emulator = Emulator::new(&rom, save_file).expect("Rom parsing failed");
+    let options = EmulatorOptions {
+        boot_rom: boot_rom.as_deref(),
+        model: opt.model.map(Into::into),
+        mapper: opt.mapper.map(Into::into),
+        time_source: Some(Box::new(gband::OffsetTime::new(
+            Box::new(wall_clock::WallClock),
+            opt.rtc_offset,
+        ))),
+        ..Default::default()
+    };
+    let mut emulator =
+        Emulator::with_options(&rom, save_file, options).expect("Rom parsing failed");
 
     // Create serial link
-    let serial_transport: Box<dyn gband::SerialTransport> = match (opt.client, opt.server) {
-        (Some(addr), _) => Box::new(socket_serial_transport::SocketSerialTransport::new(
+    let serial_transport: Box<dyn gband::SerialTransport> = match (opt.client, opt.server, opt.hub)
+    {
+        (Some(addr), _, _) => Box::new(socket_serial_transport::SocketSerialTransport::new(
             addr, false,
         )),
-        (_, Some(addr)) => Box::new(socket_serial_transport::SocketSerialTransport::new(
+        (_, Some(addr), _) => Box::new(socket_serial_transport::SocketSerialTransport::new(
             addr, true,
         )),
+        (_, _, Some(addr)) => {
+            dmg07_hub::start(addr).expect("Could not start the four player adapter");
+            Box::new(socket_serial_transport::SocketSerialTransport::new(
+                addr, false,
+            ))
+        }
+        _ if opt.printer => Box::new(gband::PrinterSerialTransport::new(Box::new(move |page| {
+            save_printed_page(&print_path, page)
+        }))),
         _ => Box::new(gband::NullSerialTransport),
     };
 
     emulator.set_serial(serial_transport);
 
+    // Create IR link
+    let ir_transport: Box<dyn gband::IrTransport> = match (opt.ir_client, opt.ir_server) {
+        (Some(addr), _) => Box::new(socket_ir_transport::SocketIrTransport::new(addr, false)),
+        (_, Some(addr)) => Box::new(socket_ir_transport::SocketIrTransport::new(addr, true)),
+        _ => Box::new(gband::NullIrTransport),
+    };
+
+    emulator.set_ir(ir_transport);
+
+    if let Some(path) = opt.camera_image {
+        let image = image::open(path)
+            .expect("Could not read the camera image")
+            .into_luma8();
+
+        emulator.set_image_source(Box::new(gband::StaticImage::new(
+            image.as_raw(),
+            image.width() as usize,
+            image.height() as usize,
+        )));
+    }
+
+    // Create the window, larger on the SGB to fit the border
+    let (screen_width, screen_height) = emulator.screen_size();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("GBAND")
+        .with_inner_size(winit::dpi::LogicalSize::new(
+            screen_width as f32 * 4.0,
+            screen_height as f32 * 4.0,
+        ))
+        .with_window_icon(Some(icon))
+        .build(&event_loop)
+        .unwrap();
+
     #[cfg(feature = "gilrs")]
     // Setup Gamepad support
     let gamepad_events = if !opt.disable_gamepad {
@@ -841,6 +1202,43 @@ fn main() {
                     } => {
                         state.pause();
                     }
+
+                    // Quick save and quick load
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F5),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.save_state(&state_path);
+                    }
+
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.load_state(&state_path);
+                    }
+
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F6),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.next_compatibility_palette();
+                    }
                     _ => {}
                 }
             }
@@ -0,0 +1,130 @@
+use gband::IrTransport;
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+/// Time between two connection attempts, as the sensor is polled on every read
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Sends the state of the LED as a byte on every change, and keeps the last byte received as the light
+pub struct SocketIrTransport {
+    address: SocketAddr,
+    server: bool,
+
+    listener: Option<TcpListener>,
+    socket: Option<TcpStream>,
+    last_attempt: Option<Instant>,
+
+    light: bool,
+}
+
+impl SocketIrTransport {
+    pub fn new(address: SocketAddr, server: bool) -> Self {
+        Self {
+            address,
+            server,
+
+            listener: None,
+            socket: None,
+            last_attempt: None,
+
+            light: false,
+        }
+    }
+
+    fn connect(&mut self) -> Option<&mut TcpStream> {
+        let retry = self
+            .last_attempt
+            .is_none_or(|attempt| attempt.elapsed() >= RETRY_DELAY);
+
+        if self.socket.is_none() && retry {
+            self.last_attempt = Some(Instant::now());
+
+            let socket = if self.server {
+                self.accept()
+            } else {
+                match TcpStream::connect_timeout(&self.address, Duration::from_millis(100)) {
+                    Ok(socket) => Some(socket),
+                    Err(e) => {
+                        log::error!("Failed to connect the IR: {}", e);
+                        None
+                    }
+                }
+            };
+
+            if let Some(socket) = socket {
+                log::info!("IR connected with {}", self.address);
+
+                if let Err(e) = socket.set_nonblocking(true) {
+                    log::warn!("Could not set the socket to non-blocking! {e}");
+                };
+
+                self.socket = Some(socket);
+            }
+        }
+
+        self.socket.as_mut()
+    }
+
+    fn accept(&mut self) -> Option<TcpStream> {
+        if self.listener.is_none() {
+            match TcpListener::bind(self.address) {
+                Ok(listener) => {
+                    log::info!("Started IR listener on {}", self.address);
+
+                    if let Err(e) = listener.set_nonblocking(true) {
+                        log::warn!("Could not set the listener to non-blocking! {e}");
+                    };
+
+                    self.listener = Some(listener);
+                }
+                Err(e) => log::error!("Unable to create IR listener: {}", e),
+            }
+        }
+
+        match self.listener.as_ref()?.accept() {
+            Ok((socket, _)) => Some(socket),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => None,
+            Err(e) => {
+                log::error!("IR socket accept failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl IrTransport for SocketIrTransport {
+    fn set_led(&mut self, on: bool) {
+        if let Some(socket) = self.connect() {
+            if let Err(e) = socket.write(&[on as u8]) {
+                log::warn!("Couldn't write to the IR socket: {e}");
+                self.socket = None;
+            }
+        }
+    }
+
+    fn recv(&mut self) -> bool {
+        let mut recv_buf = [0u8; 64];
+
+        while let Some(socket) = self.connect() {
+            match socket.read(&mut recv_buf) {
+                Ok(0) => {
+                    log::warn!("The IR socket was closed");
+                    self.socket = None;
+                    break;
+                }
+                Ok(n) => self.light = recv_buf[n - 1] != 0,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Failed to receive from the IR socket! {e}");
+                    self.socket = None;
+                    break;
+                }
+            }
+        }
+
+        self.light
+    }
+}
@@ -0,0 +1,15 @@
+use gband::TimeSource;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Follows the time of the host, so cartridge clocks keep running while the emulator is closed
+pub struct WallClock;
+
+impl TimeSource for WallClock {
+    fn now(&self, _emulated_seconds: u64) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default()
+    }
+}
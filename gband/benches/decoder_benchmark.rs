@@ -1,7 +1,8 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use gband::{
-    borrow_cpu_bus, Cartridge, CgbDoubleSpeed, Cpu, HDma, InterruptState, JoypadState, OamDma, Ppu,
-    RomParserError, SerialPort, TimerRegisters,
+    borrow_cpu_bus, Apu, BootRom, Cartridge, CgbDoubleSpeed, Cpu, HDma, HardwareModel,
+    InterruptState, IrPort, JoypadState, OamDma, Ppu, RomParserError, SerialPort, Sgb,
+    TimerRegisters,
 };
 use std::time::Duration;
 
@@ -17,10 +18,15 @@ struct MockEmulator {
     pub hdma: HDma,
     pub timer_registers: TimerRegisters,
     pub serial_port: SerialPort,
+    pub ir_port: IrPort,
     pub joypad_state: JoypadState,
     pub joypad_register: u8,
+    pub sgb: Option<Sgb>,
     pub ppu: Ppu,
     pub cgb_mode: bool,
+    pub apu: Apu,
+    pub boot_rom: BootRom,
+    pub model: HardwareModel,
 }
 
 impl MockEmulator {
@@ -41,10 +47,15 @@ impl MockEmulator {
             hdma: Default::default(),
             timer_registers: Default::default(),
             serial_port: Default::default(),
+            ir_port: Default::default(),
             joypad_state: Default::default(),
             joypad_register: 0,
+            sgb: None,
             ppu: Default::default(),
             cgb_mode: false,
+            apu: Default::default(),
+            boot_rom: Default::default(),
+            model: HardwareModel::Cgb,
         };
 
         Ok(emulator)
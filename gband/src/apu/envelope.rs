@@ -0,0 +1,69 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Volume envelope shared by the square and noise channels (NRx2)
+#[derive(Default, Clone)]
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, data: u8) {
+        self.register = data;
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /// The DAC is powered off when the upper 5 bits of the register are all 0
+    pub fn is_dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.register & 0x07;
+    }
+
+    /// Clocked at 64Hz by the frame sequencer
+    pub fn clock(&mut self) {
+        let pace = self.register & 0x07;
+        if pace == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = pace;
+
+            if self.register & 0x08 != 0 {
+                if self.volume < 0xF {
+                    self.volume += 1;
+                }
+            } else if self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.register = reader.read_u8()?;
+        self.volume = reader.read_u8()?;
+        self.timer = reader.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+mod envelope;
+mod length_counter;
+mod noise_channel;
+mod square_channel;
+mod wave_channel;
+
+use alloc::vec::Vec;
+
+use noise_channel::NoiseChannel;
+use square_channel::SquareChannel;
+use wave_channel::WaveChannel;
+
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Number of T-cycles per second
+const CLOCK_RATE: u32 = 4_194_304;
+
+/// Charge factor of the high-pass filter capacitor, per T-cycle
+const CAPACITOR_CHARGE_FACTOR: f32 = 0.999958;
+
+pub struct Apu {
+    channel1: SquareChannel,
+    channel2: SquareChannel,
+    channel3: WaveChannel,
+    channel4: NoiseChannel,
+
+    enabled: bool,
+    // NR50
+    master_volume: u8,
+    // NR51
+    panning: u8,
+
+    /// Sound from the cartridge, on the Vin pin
+    vin: f32,
+
+    frame_sequencer_step: u8,
+    div_latch: bool,
+
+    // == Sample generation == //
+    sample_rate: u32,
+    sample_counter: u32,
+    accumulator: (f32, f32),
+    accumulated_cycles: u32,
+    capacitor_factor: f32,
+    capacitors: (f32, f32),
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self {
+            channel1: SquareChannel::new(true),
+            channel2: SquareChannel::new(false),
+            channel3: Default::default(),
+            channel4: Default::default(),
+
+            // Values left by the boot ROM
+            enabled: true,
+            master_volume: 0x77,
+            panning: 0xF3,
+            vin: 0.0,
+
+            frame_sequencer_step: 0,
+            div_latch: false,
+
+            sample_rate: 0,
+            sample_counter: 0,
+            accumulator: (0.0, 0.0),
+            accumulated_cycles: 0,
+            capacitor_factor: 0.0,
+            capacitors: (0.0, 0.0),
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl Apu {
+    /// Advance the APU by `cycles` T-cycles.
+    /// `div` is the internal timer counter, which drives the frame sequencer.
+    pub fn clock(&mut self, cycles: u8, div: u16, double_speed: bool) {
+        if self.enabled {
+            // The frame sequencer is clocked on the falling edge of bit 4 of DIV (bit 5 in double speed)
+            let latch = div & frame_sequencer_mask(double_speed) != 0;
+            if self.div_latch && !latch {
+                self.clock_frame_sequencer();
+            }
+            self.div_latch = latch;
+
+            let cycles = cycles as u16;
+            self.channel1.clock(cycles);
+            self.channel2.clock(cycles);
+            self.channel3.clock(cycles);
+            self.channel4.clock(cycles);
+        }
+
+        if self.sample_rate != 0 {
+            self.generate_sample(cycles as u32);
+        }
+    }
+
+    /// Number of CPU cycles before the next step of the frame sequencer
+    pub fn idle_cycles(&self, div: u16, double_speed: bool) -> u32 {
+        if self.enabled {
+            crate::timer_regs::cycles_until_falling_edge(div, frame_sequencer_mask(double_speed))
+        } else {
+            u32::MAX
+        }
+    }
+
+    /// Skip `cycles` T-cycles, in steps of `step` cycles, without a frame sequencer step.
+    /// `div` is the value of DIV at the end.
+    pub fn skip(&mut self, cycles: u32, step: u8, div: u16, double_speed: bool) {
+        if self.sample_rate != 0 {
+            // The samples average the output of every step, so they can't be skipped
+            for _ in 0..cycles / step as u32 {
+                self.clock(step, div, double_speed);
+            }
+        } else if self.enabled {
+            self.div_latch = div & frame_sequencer_mask(double_speed) != 0;
+
+            let mut cycles = cycles;
+            while cycles > 0 {
+                let chunk = cycles.min(u16::MAX as u32);
+                self.channel1.clock(chunk as u16);
+                self.channel2.clock(chunk as u16);
+                self.channel3.clock(chunk as u16);
+                self.channel4.clock(chunk as u16);
+                cycles -= chunk;
+            }
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        // Length counters at 256Hz
+        if self.frame_sequencer_step & 1 == 0 {
+            self.channel1.clock_length();
+            self.channel2.clock_length();
+            self.channel3.clock_length();
+            self.channel4.clock_length();
+        }
+
+        // Sweep at 128Hz
+        if self.frame_sequencer_step & 3 == 2 {
+            self.channel1.clock_sweep();
+        }
+
+        // Envelopes at 64Hz
+        if self.frame_sequencer_step == 7 {
+            self.channel1.clock_envelope();
+            self.channel2.clock_envelope();
+            self.channel4.clock_envelope();
+        }
+
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) & 7;
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        // Wave RAM is still accessible when the APU is off
+        if let 0xFF30..=0xFF3F = addr {
+            self.channel3.write_wave_ram(addr, data);
+            return;
+        }
+
+        if addr == 0xFF26 {
+            self.write_nr52(data);
+            return;
+        }
+
+        // Registers are read-only when the APU is off
+        if !self.enabled {
+            return;
+        }
+
+        match addr {
+            0xFF10..=0xFF14 => self.channel1.write(addr - 0xFF10, data),
+            0xFF15..=0xFF19 => self.channel2.write(addr - 0xFF15, data),
+            0xFF1A..=0xFF1E => self.channel3.write(addr - 0xFF1A, data),
+            0xFF1F..=0xFF23 => self.channel4.write(addr - 0xFF1F, data),
+            0xFF24 => self.master_volume = data,
+            0xFF25 => self.panning = data,
+            _ => {}
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10..=0xFF14 => self.channel1.read(addr - 0xFF10),
+            0xFF15..=0xFF19 => self.channel2.read(addr - 0xFF15),
+            0xFF1A..=0xFF1E => self.channel3.read(addr - 0xFF1A),
+            0xFF1F..=0xFF23 => self.channel4.read(addr - 0xFF1F),
+            0xFF24 => self.master_volume,
+            0xFF25 => self.panning,
+            0xFF26 => {
+                ((self.enabled as u8) << 7)
+                    | 0x70
+                    | ((self.channel4.enabled as u8) << 3)
+                    | ((self.channel3.enabled as u8) << 2)
+                    | ((self.channel2.enabled as u8) << 1)
+                    | (self.channel1.enabled as u8)
+            }
+            0xFF30..=0xFF3F => self.channel3.read_wave_ram(addr),
+
+            // PCM12 and PCM34, CGB only
+            0xFF76 => {
+                (self.channel2.output().unwrap_or(0) << 4) | self.channel1.output().unwrap_or(0)
+            }
+            0xFF77 => {
+                (self.channel4.output().unwrap_or(0) << 4) | self.channel3.output().unwrap_or(0)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_nr52(&mut self, data: u8) {
+        let enabled = data & 0x80 != 0;
+
+        if self.enabled && !enabled {
+            // Turning the APU off clears every register except wave RAM
+            self.clear_registers();
+        } else if !self.enabled && enabled {
+            self.frame_sequencer_step = 0;
+        }
+
+        self.enabled = enabled;
+    }
+
+    fn clear_registers(&mut self) {
+        let mut channel3 = WaveChannel::default();
+        for addr in 0xFF30..=0xFF3F {
+            channel3.write_wave_ram(addr, self.channel3.read_wave_ram(addr));
+        }
+
+        self.channel1 = SquareChannel::new(true);
+        self.channel2 = SquareChannel::new(false);
+        self.channel3 = channel3;
+        self.channel4 = Default::default();
+        self.master_volume = 0;
+        self.panning = 0;
+    }
+
+    /// Restore the state left by the boot ROM, keeping the audio output configuration
+    pub fn reset(&mut self) {
+        let sample_rate = self.sample_rate;
+        *self = Default::default();
+        self.set_sample_rate(sample_rate);
+    }
+
+    /// Set the rate at which samples are generated. A rate of 0 disables sample generation.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_counter = 0;
+        self.accumulator = (0.0, 0.0);
+        self.accumulated_cycles = 0;
+        self.capacitors = (0.0, 0.0);
+        self.samples.clear();
+
+        // We don't have powf in no_std, so this is computed once here
+        self.capacitor_factor = 1.0;
+        for _ in 0..CLOCK_RATE.checked_div(sample_rate).unwrap_or(0) {
+            self.capacitor_factor *= CAPACITOR_CHARGE_FACTOR;
+        }
+    }
+
+    /// Set the level of the sound coming from the cartridge, between -1.0 and 1.0
+    pub fn set_vin(&mut self, level: f32) {
+        self.vin = level;
+    }
+
+    /// Take the generated samples, as interleaved stereo (left, right) values between -1.0 and 1.0
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        core::mem::take(&mut self.samples)
+    }
+
+    fn generate_sample(&mut self, cycles: u32) {
+        let (left, right) = self.mix();
+        self.accumulator.0 += left * cycles as f32;
+        self.accumulator.1 += right * cycles as f32;
+        self.accumulated_cycles += cycles;
+
+        self.sample_counter += self.sample_rate * cycles;
+        if self.sample_counter < CLOCK_RATE {
+            return;
+        }
+        self.sample_counter -= CLOCK_RATE;
+
+        // Average everything since the last sample to reduce aliasing
+        let left = self.accumulator.0 / self.accumulated_cycles as f32;
+        let right = self.accumulator.1 / self.accumulated_cycles as f32;
+        self.accumulator = (0.0, 0.0);
+        self.accumulated_cycles = 0;
+
+        // High-pass filter, removes the DC offset like the capacitor on the real hardware
+        let left_out = left - self.capacitors.0;
+        let right_out = right - self.capacitors.1;
+        self.capacitors.0 = left - left_out * self.capacitor_factor;
+        self.capacitors.1 = right - right_out * self.capacitor_factor;
+
+        // Drop samples if nobody drains them for a second, to avoid growing forever
+        if self.samples.len() < self.sample_rate as usize * 2 {
+            self.samples.push(left_out);
+            self.samples.push(right_out);
+        }
+    }
+
+    fn mix(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (0.0, 0.0);
+        }
+
+        let outputs = [
+            self.channel1.output(),
+            self.channel2.output(),
+            self.channel3.output(),
+            self.channel4.output(),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, output) in outputs.iter().enumerate() {
+            // Convert the digital value to analog, a disabled DAC outputs nothing
+            let analog = match output {
+                Some(x) => 1.0 - (*x as f32 / 7.5),
+                None => continue,
+            };
+
+            if self.panning & (1 << i) != 0 {
+                right += analog;
+            }
+
+            if self.panning & (0x10 << i) != 0 {
+                left += analog;
+            }
+        }
+
+        // Vin is enabled per side in NR50
+        if self.master_volume & 0x80 != 0 {
+            left += self.vin;
+        }
+
+        if self.master_volume & 0x08 != 0 {
+            right += self.vin;
+        }
+
+        let left_volume = (((self.master_volume >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.master_volume & 0x07) + 1) as f32 / 8.0;
+
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+}
+
+/// Bit of DIV clocking the frame sequencer
+fn frame_sequencer_mask(double_speed: bool) -> u16 {
+    if double_speed {
+        1 << 13
+    } else {
+        1 << 12
+    }
+}
+
+impl SaveState for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.channel1.save_state(writer);
+        self.channel2.save_state(writer);
+        self.channel3.save_state(writer);
+        self.channel4.save_state(writer);
+
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.master_volume);
+        writer.write_u8(self.panning);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_bool(self.div_latch);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.channel1.load_state(reader)?;
+        self.channel2.load_state(reader)?;
+        self.channel3.load_state(reader)?;
+        self.channel4.load_state(reader)?;
+
+        self.enabled = reader.read_bool()?;
+        self.master_volume = reader.read_u8()?;
+        self.panning = reader.read_u8()?;
+        self.frame_sequencer_step = reader.read_u8()? & 7;
+        self.div_latch = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock the APU for `n` frame sequencer steps
+    fn clock_frame_sequencer(apu: &mut Apu, n: usize) {
+        for _ in 0..n {
+            apu.clock(4, 1 << 12, false);
+            apu.clock(4, 0, false);
+        }
+    }
+
+    #[test]
+    fn test_length_counter_disables_channel() {
+        let mut apu = Apu::default();
+
+        apu.write(0xFF17, 0xF0); // NR22, max volume
+        apu.write(0xFF16, 0x3C); // NR21, length of 4
+        apu.write(0xFF19, 0xC0); // NR24, trigger with length enabled
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+
+        // Length is clocked every other step
+        clock_frame_sequencer(&mut apu, 6);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn test_power_off_clears_registers() {
+        let mut apu = Apu::default();
+
+        apu.write(0xFF30, 0x12);
+        apu.write(0xFF24, 0x55);
+        apu.write(0xFF26, 0x00);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF26), 0x70);
+
+        // Writes are ignored while off, except on wave RAM
+        apu.write(0xFF24, 0x55);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF30), 0x12);
+    }
+
+    #[test]
+    fn test_sample_generation() {
+        let mut apu = Apu::default();
+        apu.set_sample_rate(48000);
+
+        apu.write(0xFF25, 0xFF); // NR51, all channels on both sides
+        apu.write(0xFF12, 0xF0); // NR12, max volume
+        apu.write(0xFF14, 0x87); // NR14, trigger
+
+        for _ in 0..=(CLOCK_RATE / 4 / 100) {
+            apu.clock(4, 0, false);
+        }
+
+        let samples = apu.drain_samples();
+        assert_eq!(samples.len(), 480 * 2);
+        assert!(samples.iter().any(|s| *s != 0.0));
+        assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+        assert!(apu.drain_samples().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Channel 4
+#[derive(Clone)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+
+    // NR43
+    polynomial: u8,
+    lfsr: u16,
+    timer: u32,
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Default::default(),
+            polynomial: 0,
+            lfsr: 0,
+            timer: 0,
+        }
+    }
+}
+
+impl NoiseChannel {
+    /// `reg` is the index of the register, 0 being the unused NR40
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            1 => self.length.load(data & 0x3F),
+            2 => {
+                self.envelope.write(data);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.polynomial = data,
+            4 => {
+                self.length.enabled = data & 0x40 != 0;
+
+                if data & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read(&self, reg: u16) -> u8 {
+        match reg {
+            2 => self.envelope.read(),
+            3 => self.polynomial,
+            4 => ((self.length.enabled as u8) << 6) | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    fn period(&self) -> u32 {
+        let divisor = match self.polynomial & 0x07 {
+            0 => 8,
+            x => (x as u32) << 4,
+        };
+
+        divisor << (self.polynomial >> 4)
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+        self.timer = self.period();
+    }
+
+    /// Advance the LFSR timer by `cycles` T-cycles
+    pub fn clock(&mut self, cycles: u16) {
+        let mut cycles = cycles as u32;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+
+            // The LFSR is not clocked with a shift of 14 or 15
+            if self.polynomial >> 4 < 14 {
+                let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                self.lfsr = (self.lfsr >> 1) | (bit << 14);
+
+                // 7-bit mode
+                if self.polynomial & 0x08 != 0 {
+                    self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+                }
+            }
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output of the channel, from 0 to 15. Returns None if the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+
+        if self.enabled && self.lfsr & 1 == 0 {
+            Some(self.envelope.volume())
+        } else {
+            Some(0)
+        }
+    }
+}
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.polynomial);
+        writer.write_u16(self.lfsr);
+        writer.write_u32(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.polynomial = reader.read_u8()?;
+        self.lfsr = reader.read_u16()? & 0x7FFF;
+        self.timer = reader.read_u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+/// Frequency sweep unit, only present on channel 1 (NR10)
+#[derive(Default, Clone)]
+struct Sweep {
+    register: u8,
+    enabled: bool,
+    shadow_frequency: u16,
+    timer: u8,
+}
+
+impl Sweep {
+    fn pace(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = match self.pace() {
+            // A pace of 0 is treated as 8
+            0 => 8,
+            x => x,
+        };
+    }
+
+    /// Returns the next frequency. A value above 2047 overflows and disables the channel
+    fn calculate(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift();
+
+        if self.register & 0x08 != 0 {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+}
+
+impl SaveState for Sweep {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.shadow_frequency);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.register = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        self.shadow_frequency = reader.read_u16()?;
+        self.timer = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+/// Channels 1 and 2
+#[derive(Clone)]
+pub struct SquareChannel {
+    pub enabled: bool,
+    sweep: Option<Sweep>,
+    length: LengthCounter,
+    envelope: Envelope,
+
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u16,
+}
+
+impl SquareChannel {
+    pub fn new(with_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            sweep: if with_sweep {
+                Some(Default::default())
+            } else {
+                None
+            },
+            length: LengthCounter::new(64),
+            envelope: Default::default(),
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    /// `reg` is the index of the register, 0 being NRx0
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.register = data & 0x7F;
+                }
+            }
+            1 => {
+                self.duty = data >> 6;
+                self.length.load(data & 0x3F);
+            }
+            2 => {
+                self.envelope.write(data);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | data as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((data as u16 & 0x07) << 8);
+                self.length.enabled = data & 0x40 != 0;
+
+                if data & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => match &self.sweep {
+                Some(sweep) => sweep.register | 0x80,
+                None => 0xFF,
+            },
+            1 => (self.duty << 6) | 0x3F,
+            2 => self.envelope.read(),
+            4 => ((self.length.enabled as u8) << 6) | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = (2048 - self.frequency) * 4;
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.pace() != 0 || sweep.shift() != 0;
+
+            if sweep.shift() != 0 && sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Advance the frequency timer by `cycles` T-cycles
+    pub fn clock(&mut self, cycles: u16) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = (2048 - self.frequency) * 4;
+            self.duty_step = (self.duty_step + 1) & 0x07;
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let sweep = match &mut self.sweep {
+            Some(sweep) => sweep,
+            None => return,
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if sweep.enabled && sweep.pace() != 0 {
+            let frequency = sweep.calculate();
+
+            if frequency > 2047 {
+                self.enabled = false;
+            } else if sweep.shift() != 0 {
+                sweep.shadow_frequency = frequency;
+                self.frequency = frequency;
+
+                // The overflow check is done a second time with the new frequency
+                if sweep.calculate() > 2047 {
+                    self.enabled = false;
+                }
+            }
+        }
+    }
+
+    /// Digital output of the channel, from 0 to 15. Returns None if the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+
+        if self.enabled {
+            Some(
+                DUTY_PATTERNS[self.duty as usize][self.duty_step as usize] * self.envelope.volume(),
+            )
+        } else {
+            Some(0)
+        }
+    }
+}
+
+impl SaveState for SquareChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(writer);
+        }
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_step);
+        writer.write_u16(self.frequency);
+        writer.write_u16(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(reader)?;
+        }
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.duty = reader.read_u8()? & 0x03;
+        self.duty_step = reader.read_u8()? & 0x07;
+        self.frequency = reader.read_u16()? & 0x7FF;
+        self.timer = reader.read_u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use super::length_counter::LengthCounter;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Channel 3
+#[derive(Clone)]
+pub struct WaveChannel {
+    pub enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    output_level: u8,
+
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample_buffer: u8,
+
+    wave_ram: [u8; 0x10],
+}
+
+impl Default for WaveChannel {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            output_level: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            wave_ram: [0u8; 0x10],
+        }
+    }
+}
+
+impl WaveChannel {
+    /// `reg` is the index of the register, 0 being NR30
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.dac_enabled = data & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(data),
+            2 => self.output_level = (data >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | data as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((data as u16 & 0x07) << 8);
+                self.length.enabled = data & 0x40 != 0;
+
+                if data & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => ((self.dac_enabled as u8) << 7) | 0x7F,
+            2 => (self.output_level << 5) | 0x9F,
+            4 => ((self.length.enabled as u8) << 6) | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_wave_ram(&mut self, addr: u16, data: u8) {
+        self.wave_ram[(addr & 0x0F) as usize] = data;
+    }
+
+    pub fn read_wave_ram(&self, addr: u16) -> u8 {
+        self.wave_ram[(addr & 0x0F) as usize]
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.position = 0;
+
+        // There is a small delay before the first sample is read
+        self.timer = (2048 - self.frequency) * 2 + 6;
+    }
+
+    /// Advance the frequency timer by `cycles` T-cycles
+    pub fn clock(&mut self, cycles: u16) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = (2048 - self.frequency) * 2;
+
+            self.position = (self.position + 1) & 0x1F;
+            self.sample_buffer = self.wave_ram[(self.position >> 1) as usize];
+        }
+
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Digital output of the channel, from 0 to 15. Returns None if the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+
+        if !self.enabled {
+            return Some(0);
+        }
+
+        // Upper nibble is played first
+        let sample = if self.position & 1 == 0 {
+            self.sample_buffer >> 4
+        } else {
+            self.sample_buffer & 0x0F
+        };
+
+        Some(match self.output_level {
+            0 => 0,
+            x => sample >> (x - 1),
+        })
+    }
+}
+
+impl SaveState for WaveChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        self.length.save_state(writer);
+        writer.write_u8(self.output_level);
+        writer.write_u16(self.frequency);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.position);
+        writer.write_u8(self.sample_buffer);
+        writer.write_bytes(&self.wave_ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.length.load_state(reader)?;
+        self.output_level = reader.read_u8()? & 0x03;
+        self.frequency = reader.read_u16()? & 0x7FF;
+        self.timer = reader.read_u16()?;
+        self.position = reader.read_u8()? & 0x1F;
+        self.sample_buffer = reader.read_u8()?;
+        reader.read_bytes(&mut self.wave_ram)?;
+        Ok(())
+    }
+}
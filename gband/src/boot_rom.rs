@@ -0,0 +1,84 @@
+use alloc::vec::Vec;
+
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::RomParserError;
+
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// Boot ROM mapped over the cartridge until a write to 0xFF50
+#[derive(Default)]
+pub struct BootRom {
+    data: Vec<u8>,
+    mapped: bool,
+
+    // KEY0, only writable while the boot ROM is mapped
+    key0: u8,
+}
+
+impl BootRom {
+    pub fn new(data: &[u8]) -> Result<Self, RomParserError> {
+        match data.len() {
+            DMG_BOOT_ROM_SIZE | CGB_BOOT_ROM_SIZE => Ok(Self {
+                data: data.to_vec(),
+                mapped: true,
+                key0: 0,
+            }),
+            _ => Err(RomParserError::InvalidBootRom),
+        }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.data.len() == CGB_BOOT_ROM_SIZE
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// Returns the byte of the boot ROM if it is mapped at this address
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if !self.mapped {
+            return None;
+        }
+
+        match addr {
+            // 0x100-0x1FF is always the cartridge header
+            0x0000..=0x00FF | 0x0200..=0x08FF => self.data.get(addr as usize).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn unmap(&mut self) {
+        self.mapped = false;
+    }
+
+    pub fn write_key0(&mut self, data: u8) {
+        if self.mapped {
+            self.key0 = data;
+        }
+    }
+
+    pub fn read_key0(&self) -> u8 {
+        self.key0
+    }
+
+    /// The CGB boot ROM writes 0x04 to KEY0 for DMG cartridges
+    pub fn is_dmg_compatibility(&self) -> bool {
+        self.key0 & 0x0C == 0x04
+    }
+}
+
+impl SaveState for BootRom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        // The image itself is provided by the user, so only the registers are saved
+        writer.write_bool(self.mapped);
+        writer.write_u8(self.key0);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mapped = reader.read_bool()?;
+        self.key0 = reader.read_u8()?;
+        Ok(())
+    }
+}
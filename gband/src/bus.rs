@@ -1,11 +1,16 @@
+use crate::boot_rom::BootRom;
 use crate::dma::*;
+use crate::Apu;
 use crate::Cartridge;
 use crate::CgbDoubleSpeed;
+use crate::HardwareModel;
 use crate::InterruptReg;
 use crate::InterruptState;
+use crate::IrPort;
 use crate::JoypadState;
 use crate::Ppu;
 use crate::SerialPort;
+use crate::Sgb;
 use crate::TimerRegisters;
 use crate::WRAM_BANK_SIZE;
 
@@ -27,9 +32,14 @@ macro_rules! borrow_cpu_bus {
             &mut $owner.cartridge,
             &mut $owner.ppu,
             &mut $owner.cgb_mode,
+            &mut $owner.apu,
+            &mut $owner.boot_rom,
+            &$owner.model,
             &mut $owner.serial_port,
+            &mut $owner.ir_port,
             &$owner.joypad_state,
             &mut $owner.joypad_register,
+            &mut $owner.sgb,
         )
     }};
 }
@@ -46,9 +56,14 @@ pub struct CpuBus<'a> {
     cartridge: &'a mut Cartridge,
     ppu: &'a mut Ppu,
     cgb_mode: &'a mut bool,
+    apu: &'a mut Apu,
+    boot_rom: &'a mut BootRom,
+    model: &'a HardwareModel,
     serial_port: &'a mut SerialPort,
+    ir_port: &'a mut IrPort,
     joypad_state: &'a JoypadState,
     joypad_register: &'a mut u8,
+    sgb: &'a mut Option<Sgb>,
 }
 
 impl<'a> CpuBus<'a> {
@@ -65,9 +80,14 @@ impl<'a> CpuBus<'a> {
         cartridge: &'a mut Cartridge,
         ppu: &'a mut Ppu,
         cgb_mode: &'a mut bool,
+        apu: &'a mut Apu,
+        boot_rom: &'a mut BootRom,
+        model: &'a HardwareModel,
         serial_port: &'a mut SerialPort,
+        ir_port: &'a mut IrPort,
         joypad_state: &'a JoypadState,
         joypad_register: &'a mut u8,
+        sgb: &'a mut Option<Sgb>,
     ) -> Self {
         Self {
             wram,
@@ -81,9 +101,14 @@ impl<'a> CpuBus<'a> {
             cartridge,
             ppu,
             cgb_mode,
+            apu,
+            boot_rom,
+            model,
             serial_port,
+            ir_port,
             joypad_state,
             joypad_register,
+            sgb,
         }
     }
 }
@@ -96,7 +121,7 @@ impl CpuBus<'_> {
                 source,
             } => {
                 // Wraps regular CPU writes to disallow conflicting bus access during OAM_DMA
-                if !Self::check_oam_dma_bus_conflict(*source, addr) {
+                if !Self::check_oam_dma_bus_conflict(*self.model, *source, addr) {
                     self.write_without_dma_check(addr, data, false)
                 }
             }
@@ -104,14 +129,16 @@ impl CpuBus<'_> {
         }
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.sample_ir(addr);
+
         match self.oam_dma.clone() {
             OamDma {
                 cycle: Some(_),
                 source,
             } => {
                 // Wraps regular CPU reads to disallow conflicting bus access during OAM_DMA
-                if !Self::check_oam_dma_bus_conflict(source, addr) {
+                if !Self::check_oam_dma_bus_conflict(*self.model, source, addr) {
                     self.read_without_dma_check(addr, false)
                 } else {
                     0xFF
@@ -157,6 +184,10 @@ impl CpuBus<'_> {
             }
             0xFF04..=0xFF07 => self.timer_registers.write(addr, data),
             0xFF0F => self.interrupts.status = InterruptReg::from_bits_truncate(0xE0 | data),
+            0xFF10..=0xFF3F => {
+                // APU
+                self.apu.write(addr, data)
+            }
             0xFF46 => {
                 // OAM DMA
                 self.request_oam_dma(data)
@@ -178,20 +209,36 @@ impl CpuBus<'_> {
                     self.ppu.disable();
                 };
             }
-            0xFF40..=0xFF45 | 0xFF47..=0xFF4C | 0xFF4E..=0xFF50 | 0xFF56..=0xFF6F => {
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => {
                 // PPU control regs
                 self.ppu.write(addr, data)
             }
-            0xFF4D => {
+            0xFF56 if *self.cgb_mode => {
+                // Infrared port (RP)
+                self.ir_port.write(data)
+            }
+            0xFF4F | 0xFF57..=0xFF6F if *self.cgb_mode => {
+                // CGB PPU control regs
+                self.ppu.write(addr, data)
+            }
+            0xFF4C if *self.cgb_mode => {
+                // KEY0
+                self.boot_rom.write_key0(data)
+            }
+            0xFF50 if data & 1 != 0 => {
+                // Boot ROM unmap
+                self.unmap_boot_rom()
+            }
+            0xFF4D if *self.cgb_mode => {
                 // KEY1
                 self.double_speed
                     .set(CgbDoubleSpeed::PENDING, (data & 1) != 0)
             }
-            0xFF51..=0xFF55 => {
+            0xFF51..=0xFF55 if *self.cgb_mode => {
                 // HDMA
                 self.write_hdma(addr, data)
             }
-            0xFF70 => *self.wram_bank = data,
+            0xFF70 if *self.cgb_mode => *self.wram_bank = data,
             0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = data,
             0xFFFF => self.interrupts.enable = InterruptReg::from_bits_truncate(data),
             _ => {
@@ -236,27 +283,39 @@ impl CpuBus<'_> {
             }
             0xFF04..=0xFF07 => self.timer_registers.read(addr),
             0xFF0F => self.interrupts.status.bits(),
-            0xFF26 => {
-                // NR52, mock for now to make Zelda games work
-                0x00
+            0xFF10..=0xFF3F => {
+                // APU
+                self.apu.read(addr)
             }
             0xFF46 => {
                 // OAM DMA
                 self.read_oam_dma()
             }
-            0xFF40..=0xFF45 | 0xFF47..=0xFF4C | 0xFF4E..=0xFF50 | 0xFF56..=0xFF6F => {
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => {
                 // PPU control reg
                 self.ppu.read(addr)
             }
-            0xFF4D => {
+            0xFF56 if *self.cgb_mode => {
+                // Infrared port (RP)
+                self.ir_port.read()
+            }
+            0xFF4F | 0xFF57..=0xFF6F if *self.cgb_mode => {
+                // CGB PPU control reg
+                self.ppu.read(addr)
+            }
+            0xFF4D if *self.cgb_mode => {
                 // KEY1
                 self.double_speed.bits()
             }
-            0xFF51..=0xFF55 => {
+            0xFF51..=0xFF55 if *self.cgb_mode => {
                 // HDMA
                 self.read_hdma(addr)
             }
-            0xFF70 => *self.wram_bank,
+            0xFF70 if *self.cgb_mode => *self.wram_bank,
+            0xFF76 | 0xFF77 if *self.cgb_mode => {
+                // PCM12 and PCM34
+                self.apu.read(addr)
+            }
             0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
             0xFFFF => self.interrupts.enable.bits(),
             _ => {
@@ -311,11 +370,45 @@ impl CpuBus<'_> {
     }
 
     pub fn write_cartridge(&mut self, addr: u16, data: u8) {
-        self.cartridge.write(addr, data)
+        self.cartridge.write(addr, data);
+        self.ir_port.set_cartridge_led(self.cartridge.ir_led());
+    }
+
+    /// Sample the IR sensor right before the CGB or the cartridge reads it
+    fn sample_ir(&mut self, addr: u16) {
+        match addr {
+            0xFF56 if *self.cgb_mode => self.ir_port.sample(),
+            0xA000..=0xBFFF => {
+                if self.ir_port.cartridge_light().is_some() {
+                    self.ir_port.sample();
+                }
+
+                if let Some(light) = self.ir_port.cartridge_light() {
+                    self.cartridge.set_ir_light(light);
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn read_cartridge(&self, addr: u16) -> u8 {
-        self.cartridge.read(addr)
+        match self.boot_rom.read(addr) {
+            Some(data) => data,
+            None => self.cartridge.read(addr),
+        }
+    }
+
+    fn unmap_boot_rom(&mut self) {
+        if !self.boot_rom.is_mapped() {
+            return;
+        }
+
+        self.boot_rom.unmap();
+
+        if self.boot_rom.is_cgb() && self.boot_rom.is_dmg_compatibility() {
+            *self.cgb_mode = false;
+            self.ppu.set_compatibility_mode();
+        }
     }
 
     fn write_hdma(&mut self, addr: u16, data: u8) {
@@ -358,7 +451,16 @@ impl CpuBus<'_> {
     }
 
     pub fn write_joypad_reg(&mut self, data: u8) {
-        let state: u8 = (*self.joypad_state).bits();
+        let mut state: u8 = (*self.joypad_state).bits();
+
+        if let Some(sgb) = self.sgb.as_mut() {
+            sgb.write_joypad(data);
+
+            // Only the first joypad is connected
+            if sgb.player() != 0 {
+                state = 0;
+            }
+        }
 
         // Defaults to no button pressed
         *self.joypad_register = 0;
@@ -376,6 +478,13 @@ impl CpuBus<'_> {
         // Invert button presses
         *self.joypad_register = !*self.joypad_register;
 
+        // With both lines high, the SGB returns the joypad it's reading, counting down from 0xF
+        if let Some(sgb) = self.sgb.as_ref() {
+            if data & 0x30 == 0x30 && sgb.is_multiplayer() {
+                *self.joypad_register = 0x0F - sgb.player();
+            }
+        }
+
         // Mask to get only the buttons + add the input bits
         *self.joypad_register = (*self.joypad_register & 0x0F) | (data & 0x30)
     }
@@ -438,12 +547,15 @@ impl CpuBus<'_> {
         self.interrupts.status.insert(interrupt)
     }
 
-    fn check_oam_dma_bus_conflict(source: u8, addr: u16) -> bool {
-        // Bus on CGB are emulated.
+    fn check_oam_dma_bus_conflict(model: HardwareModel, source: u8, addr: u16) -> bool {
         match (source, addr) {
             // ROM and SRAM shares the same bus
             (0x00..=0x7F | 0xA0..=0xBF, 0x0000..=0x7FFF | 0xA000..=0xBFFF) => true,
-            // WRAM has it's own bus.
+            // Before the CGB, WRAM is also on the external bus with ROM and SRAM
+            (0x00..=0x7F | 0xA0..=0xFD, 0x0000..=0x7FFF | 0xA000..=0xFDFF) if !model.is_cgb() => {
+                true
+            }
+            // On CGB, WRAM has it's own bus.
             (0xC0..=0xFD, 0xC000..=0xFDFF) => true,
             // VRAM has it's own bus, which is always blocked because it's the destination
             (_, 0x8000..=0x9FFF) => true,
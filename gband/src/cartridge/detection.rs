@@ -0,0 +1,104 @@
+use super::header::{CartridgeType, NINTENDO_LOGO};
+
+/// Mapper of a cartridge, which can be forced when the header lies about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperKind {
+    NoMapper,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    Mmm01,
+    HuC1,
+    HuC3,
+    PocketCamera,
+    Tama5,
+    WisdomTree,
+    SachenMmc1,
+    M161,
+}
+
+impl From<CartridgeType> for MapperKind {
+    fn from(cartridge_type: CartridgeType) -> Self {
+        match cartridge_type {
+            CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery => {
+                MapperKind::NoMapper
+            }
+            CartridgeType::Mbc1 | CartridgeType::Mbc1Ram | CartridgeType::Mbc1RamBattery => {
+                MapperKind::Mbc1
+            }
+            CartridgeType::Mbc2 | CartridgeType::Mbc2Battery => MapperKind::Mbc2,
+            CartridgeType::Mmm01 | CartridgeType::Mmm01Ram | CartridgeType::Mmm01RamBattery => {
+                MapperKind::Mmm01
+            }
+            CartridgeType::Mbc3TimerBattery
+            | CartridgeType::Mbc3TimerRamBattery
+            | CartridgeType::Mbc3
+            | CartridgeType::Mbc3Ram
+            | CartridgeType::Mbc3RamBattery => MapperKind::Mbc3,
+            CartridgeType::Mbc5
+            | CartridgeType::Mbc5Ram
+            | CartridgeType::Mbc5RamBattery
+            | CartridgeType::Mbc5Rumble
+            | CartridgeType::Mbc5RumbleRam
+            | CartridgeType::Mbc5RumbleRamBattery => MapperKind::Mbc5,
+            CartridgeType::Mbc6 => MapperKind::Mbc6,
+            CartridgeType::Mbc7SensorRumbleRamBattery => MapperKind::Mbc7,
+            CartridgeType::Huc1RamBattery => MapperKind::HuC1,
+            CartridgeType::Huc3 => MapperKind::HuC3,
+            CartridgeType::PocketCamera => MapperKind::PocketCamera,
+            CartridgeType::BandaiTama5 => MapperKind::Tama5,
+        }
+    }
+}
+
+/// Looks for the signatures of unlicensed mappers, whose header can't be trusted.
+/// Returns None for a licensed cartridge.
+pub fn detect_unlicensed_mapper(rom: &[u8]) -> Option<MapperKind> {
+    if rom.len() <= 0x8000 {
+        // Nothing to map
+        return None;
+    }
+
+    let title = &rom[0x134..0x144];
+    let first_bank = &rom[..0x8000];
+
+    if contains(first_bank, b"WISDOM TREE") || contains(first_bank, b"WISDOM\x00TREE") {
+        Some(MapperKind::WisdomTree)
+    } else if title.starts_with(b"TETRIS SET") {
+        Some(MapperKind::M161)
+    } else if has_sachen_logo(rom) {
+        Some(MapperKind::SachenMmc1)
+    } else if rom[0x147] == 0x00 {
+        // Bootleg boards often say they are ROM only while holding more than 32 KiB.
+        // Most are wired like an MBC1, or an MBC5 past the 2 MiB an MBC1 can address.
+        if rom.len() <= 0x200000 {
+            Some(MapperKind::Mbc1)
+        } else {
+            Some(MapperKind::Mbc5)
+        }
+    } else {
+        None
+    }
+}
+
+fn contains(data: &[u8], pattern: &[u8]) -> bool {
+    data.windows(pattern.len()).any(|window| window == pattern)
+}
+
+/// Sachen cartridges hold their own logo, and the real one at the addresses scrambled by the boot ROM check.
+/// The mapper swaps address lines A0 with A6, and A1 with A4.
+fn has_sachen_logo(rom: &[u8]) -> bool {
+    let scramble = |addr: usize| {
+        let swapped = ((addr & 0x01) << 6)
+            | ((addr & 0x40) >> 6)
+            | ((addr & 0x02) << 3)
+            | ((addr & 0x10) >> 3);
+        (addr & !0x53) | swapped
+    };
+
+    rom[0x104..0x134] != NINTENDO_LOGO
+        && (0..0x30).all(|i| rom[scramble(0x104 + i)] == NINTENDO_LOGO[i])
+}
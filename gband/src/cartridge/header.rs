@@ -6,6 +6,7 @@ pub enum RomParserError {
     UnknownMapper,
     MapperNotImplemented,
     InvalidChecksum,
+    InvalidBootRom,
 }
 
 impl core::fmt::Display for RomParserError {
@@ -14,6 +15,13 @@ impl core::fmt::Display for RomParserError {
     }
 }
 
+/// Logo checked by the boot ROM, at 0x104 in every cartridge
+pub const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
 #[derive(Clone, Copy, Debug)]
 pub enum CgbFlag {
     NoCgb,
@@ -54,6 +62,15 @@ pub enum CartridgeType {
     Huc1RamBattery = 0xFF,
 }
 
+impl CartridgeType {
+    pub fn is_mmm01(&self) -> bool {
+        matches!(
+            self,
+            CartridgeType::Mmm01 | CartridgeType::Mmm01Ram | CartridgeType::Mmm01RamBattery
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum RamBanks {
     None,
@@ -84,6 +101,19 @@ impl TryFrom<&[u8]> for Header {
     type Error = RomParserError;
 
     fn try_from(data: &[u8]) -> Result<Self, RomParserError> {
+        Self::parse(data, true)
+    }
+}
+
+impl Header {
+    /// Parses a header without checking the cartridge type and the checksum,
+    /// for unlicensed cartridges which often get them wrong.
+    /// An unknown cartridge type is read as ROM only.
+    pub fn parse_unverified(data: &[u8]) -> Result<Self, RomParserError> {
+        Self::parse(data, false)
+    }
+
+    fn parse(data: &[u8], verify: bool) -> Result<Self, RomParserError> {
         if data.len() < 0x50 {
             return Err(RomParserError::TooShort);
         };
@@ -108,10 +138,10 @@ impl TryFrom<&[u8]> for Header {
 
         let sgb_flag = data[0x46] == 0x03;
 
-        let cartridge_type = if let Ok(t) = CartridgeType::try_from(data[0x47]) {
-            t
-        } else {
-            return Err(RomParserError::UnknownMapper);
+        let cartridge_type = match CartridgeType::try_from(data[0x47]) {
+            Ok(t) => t,
+            Err(_) if !verify => CartridgeType::RomOnly,
+            Err(_) => return Err(RomParserError::UnknownMapper),
         };
 
         let rom_banks = 0b10usize.wrapping_shl(data[0x48].into());
@@ -142,7 +172,7 @@ impl TryFrom<&[u8]> for Header {
             checksum = checksum.wrapping_sub(*b as usize).wrapping_sub(1);
         }
 
-        if (checksum & 0xff) as u8 != header_checksum {
+        if verify && (checksum & 0xff) as u8 != header_checksum {
             return Err(RomParserError::InvalidChecksum);
         };
 
@@ -0,0 +1,198 @@
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Values read from the IR register when light is received or not
+pub const IR_LIGHT: u8 = 0xC1;
+pub const IR_NO_LIGHT: u8 = 0xC0;
+
+pub struct HuC1 {
+    /// 0xA000-0xBFFF maps the IR LED and sensor instead of the RAM
+    ir_mode: bool,
+    ir_led: bool,
+    /// Sampled from the IR transport while the IR is mapped
+    ir_light: bool,
+    rom_bank_number: u8,
+    ram_bank_number: u8,
+}
+
+impl HuC1 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for HuC1 {
+    fn default() -> Self {
+        Self {
+            ir_mode: false,
+            ir_led: false,
+            ir_light: false,
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+        }
+    }
+}
+
+impl Mapper for HuC1 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 14usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    CartridgeReadTarget::Value(if self.ir_light { IR_LIGHT } else { IR_NO_LIGHT })
+                } else {
+                    // The RAM doesn't need to be enabled
+                    let mask = 0x1FFF;
+                    let addr = (addr & mask) as usize;
+                    let bank = (self.ram_bank_number as usize) << 13usize;
+
+                    CartridgeReadTarget::Ram(bank | addr)
+                }
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                // Selects between the RAM and the IR
+                self.ir_mode = data & 0x0F == 0x0E;
+                None
+            }
+            0x2000..=0x3FFF => {
+                // ROM bank number
+                let bank_number = data & 0x3F;
+                if bank_number == 0 {
+                    self.rom_bank_number = 0x01;
+                } else {
+                    self.rom_bank_number = bank_number;
+                }
+                None
+            }
+            0x4000..=0x5FFF => {
+                // RAM bank number
+                self.ram_bank_number = data & 0x03;
+                None
+            }
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    self.ir_led = data & 0x01 == 0x01;
+                    None
+                } else {
+                    let mask = 0x1FFF;
+                    let addr = (addr & mask) as usize;
+                    let bank = (self.ram_bank_number as usize) << 13usize;
+
+                    Some(bank | addr)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn ir_led(&self) -> Option<bool> {
+        self.ir_mode.then_some(self.ir_led)
+    }
+
+    fn set_ir_light(&mut self, light: bool) {
+        self.ir_light = light;
+    }
+}
+
+impl SaveState for HuC1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ir_mode);
+        writer.write_bool(self.ir_led);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ir_mode = reader.read_bool()?;
+        self.ir_led = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_bank() {
+        let mut huc1 = HuC1::new();
+
+        // Bank 0 can't be mapped at 0x4000
+        huc1.map_write(0x2000, 0x00);
+        assert!(matches!(
+            huc1.map_read(0x4000),
+            CartridgeReadTarget::Rom(0x4000)
+        ));
+
+        huc1.map_write(0x2000, 0x42);
+        assert!(matches!(
+            huc1.map_read(0x4001),
+            CartridgeReadTarget::Rom(0x8001)
+        ));
+    }
+
+    #[test]
+    fn test_ir() {
+        let mut huc1 = HuC1::new();
+
+        // RAM is mapped until the IR is selected
+        huc1.map_write(0x4000, 0x01);
+        assert!(matches!(
+            huc1.map_read(0xA000),
+            CartridgeReadTarget::Ram(0x2000)
+        ));
+        assert_eq!(huc1.map_write(0xA000, 0x01), Some(0x2000));
+        assert_eq!(huc1.ir_led(), None);
+
+        huc1.map_write(0x0000, 0x0E);
+        assert_eq!(huc1.ir_led(), Some(false));
+        assert!(matches!(
+            huc1.map_read(0xA000),
+            CartridgeReadTarget::Value(IR_NO_LIGHT)
+        ));
+
+        assert_eq!(huc1.map_write(0xA000, 0x01), None);
+        assert_eq!(huc1.ir_led(), Some(true));
+
+        huc1.set_ir_light(true);
+        assert!(matches!(
+            huc1.map_read(0xA000),
+            CartridgeReadTarget::Value(IR_LIGHT)
+        ));
+
+        // Going back to the RAM
+        huc1.map_write(0x0000, 0x0A);
+        assert_eq!(huc1.ir_led(), None);
+        assert!(matches!(
+            huc1.map_read(0xA000),
+            CartridgeReadTarget::Ram(0x2000)
+        ));
+    }
+}
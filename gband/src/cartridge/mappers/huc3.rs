@@ -0,0 +1,361 @@
+use alloc::vec::Vec;
+
+use super::huc1::{IR_LIGHT, IR_NO_LIGHT};
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// The clock counts minutes and 12 bits of days, so it wraps after 4096 days
+const SECONDS_WRAP: u32 = 4096 * 24 * 60 * 60;
+
+/// Size of the RTC footer: the clock, the timestamp and the RTC memory
+const FOOTER_LEN: usize = 4 + 8 + 256;
+
+/// Period of the speaker's beep, in T-cycles. This is about 2 KHz.
+const TONE_PERIOD: u64 = 2048;
+
+/// Clock of the HuC3, controlled by sending commands and reading back responses
+struct Rtc {
+    /// Time counted by the clock, in seconds
+    seconds: u32,
+
+    /// UNIX time matching `seconds`
+    timestamp: Option<u64>,
+
+    /// 256 nibbles of memory, with the time at 0x00-0x05 and the speaker settings at 0x26-0x27
+    memory: [u8; 256],
+    address: u8,
+
+    command: u8,
+    response: u8,
+
+    /// Set when the speaker is beeping
+    tone: bool,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self {
+            seconds: 0,
+            timestamp: None,
+            memory: [0; 256],
+            address: 0,
+            command: 0,
+            response: 0,
+            tone: false,
+        }
+    }
+}
+
+impl Rtc {
+    fn execute(&mut self) {
+        let argument = self.command & 0x0F;
+
+        match (self.command >> 4) & 0x07 {
+            0x1 => {
+                // Read and increment the address
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                // Write and increment the address
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | (argument << 4),
+            0x6 => match argument {
+                0x0 => {
+                    // Copy the time to memory, in minutes of the day and days
+                    let minutes = (self.seconds / 60) % (24 * 60);
+                    let days = self.seconds / (24 * 60 * 60);
+
+                    for i in 0..3 {
+                        self.memory[i] = ((minutes >> (i * 4)) & 0xF) as u8;
+                        self.memory[3 + i] = ((days >> (i * 4)) & 0xF) as u8;
+                    }
+                }
+                0x1 => {
+                    // Set the time from memory
+                    let mut minutes = 0;
+                    let mut days = 0;
+                    for i in 0..3 {
+                        minutes |= (self.memory[i] as u32) << (i * 4);
+                        days |= (self.memory[3 + i] as u32) << (i * 4);
+                    }
+
+                    self.seconds = (days * 24 * 60 + minutes) * 60 % SECONDS_WRAP;
+                }
+                0x2 => {
+                    // Status, always ready
+                    self.response = 0x1;
+                }
+                0xE => {
+                    // The speaker is enabled in memory before triggering it
+                    self.tone = self.memory[0x26] & 0x1 == 0x1;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn set_time(&mut self, now: u64) {
+        // Time going backwards, like with a new time source, doesn't move the clock back
+        if let Some(timestamp) = self.timestamp {
+            if now > timestamp {
+                let elapsed = ((now - timestamp) % SECONDS_WRAP as u64) as u32;
+                self.seconds = (self.seconds + elapsed) % SECONDS_WRAP;
+            }
+        }
+
+        self.timestamp = Some(now);
+    }
+}
+
+pub struct HuC3 {
+    /// Selects what is mapped at 0xA000-0xBFFF
+    mode: u8,
+    ir_led: bool,
+    /// Sampled from the IR transport while the IR is mapped
+    ir_light: bool,
+    rom_bank_number: u8,
+    ram_bank_number: u8,
+    rtc: Rtc,
+}
+
+impl HuC3 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        let mask = 0x1FFF;
+        let addr = (addr & mask) as usize;
+        let bank = (self.ram_bank_number as usize) << 13usize;
+
+        bank | addr
+    }
+}
+
+impl Default for HuC3 {
+    fn default() -> Self {
+        Self {
+            mode: 0x00,
+            ir_led: false,
+            ir_light: false,
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+            rtc: Default::default(),
+        }
+    }
+}
+
+impl Mapper for HuC3 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 14usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            0xA000..=0xBFFF => match self.mode {
+                // RAM, read-only in mode 0
+                0x0 | 0xA => CartridgeReadTarget::Ram(self.ram_addr(addr)),
+                // Response of the RTC, along with the command
+                0xC => CartridgeReadTarget::Value((self.rtc.command & 0xF0) | self.rtc.response),
+                // Commands are executed instantly, so the RTC is always ready
+                0xD => CartridgeReadTarget::Value(0xFF),
+                0xE if self.ir_light => CartridgeReadTarget::Value(IR_LIGHT),
+                0xE => CartridgeReadTarget::Value(IR_NO_LIGHT),
+                _ => CartridgeReadTarget::Value(0xFF),
+            },
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                // Selects what is mapped at 0xA000-0xBFFF
+                self.mode = data & 0x0F;
+                None
+            }
+            0x2000..=0x3FFF => {
+                // ROM bank number
+                self.rom_bank_number = data & 0x7F;
+                None
+            }
+            0x4000..=0x5FFF => {
+                // RAM bank number
+                self.ram_bank_number = data & 0x03;
+                None
+            }
+            0xA000..=0xBFFF => match self.mode {
+                0xA => Some(self.ram_addr(addr)),
+                0xB => {
+                    // Command to send to the RTC
+                    self.rtc.command = data;
+                    None
+                }
+                0xD => {
+                    // Executes the command
+                    self.rtc.execute();
+                    None
+                }
+                0xE => {
+                    self.ir_led = data & 0x01 == 0x01;
+                    None
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn set_time(&mut self, now: u64) {
+        self.rtc.set_time(now);
+    }
+
+    fn ir_led(&self) -> Option<bool> {
+        (self.mode == 0xE).then_some(self.ir_led)
+    }
+
+    fn set_ir_light(&mut self, light: bool) {
+        self.ir_light = light;
+    }
+
+    fn audio_output(&self, cycle_count: u64) -> Option<f32> {
+        if !self.rtc.tone {
+            return None;
+        }
+
+        // Square wave
+        if cycle_count % TONE_PERIOD < TONE_PERIOD / 2 {
+            Some(1.0)
+        } else {
+            Some(-1.0)
+        }
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        footer.extend_from_slice(&self.rtc.seconds.to_le_bytes());
+        footer.extend_from_slice(&self.rtc.timestamp.unwrap_or_default().to_le_bytes());
+        footer.extend_from_slice(&self.rtc.memory);
+        footer
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FOOTER_LEN {
+            return false;
+        }
+
+        self.rtc.seconds = u32::from_le_bytes(footer[0..4].try_into().unwrap()) % SECONDS_WRAP;
+        self.rtc.timestamp = match u64::from_le_bytes(footer[4..12].try_into().unwrap()) {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+        self.rtc.memory.copy_from_slice(&footer[12..]);
+        true
+    }
+}
+
+impl SaveState for HuC3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.mode);
+        writer.write_bool(self.ir_led);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+
+        writer.write_bytes(&self.save_footer());
+        writer.write_u8(self.rtc.address);
+        writer.write_u8(self.rtc.command);
+        writer.write_u8(self.rtc.response);
+        writer.write_bool(self.rtc.tone);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mode = reader.read_u8()?;
+        self.ir_led = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+
+        let mut footer = [0u8; FOOTER_LEN];
+        reader.read_bytes(&mut footer)?;
+        self.load_footer(&footer);
+        self.rtc.address = reader.read_u8()?;
+        self.rtc.command = reader.read_u8()?;
+        self.rtc.response = reader.read_u8()?;
+        self.rtc.tone = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(huc3: &mut HuC3, command: u8) -> u8 {
+        huc3.map_write(0x0000, 0x0B);
+        huc3.map_write(0xA000, command);
+        huc3.map_write(0x0000, 0x0D);
+        huc3.map_write(0xA000, 0x00);
+        huc3.map_write(0x0000, 0x0C);
+
+        match huc3.map_read(0xA000) {
+            CartridgeReadTarget::Value(value) => value & 0x0F,
+            _ => panic!("The RTC isn't mapped"),
+        }
+    }
+
+    #[test]
+    fn test_rtc() {
+        let mut huc3 = HuC3::new();
+        huc3.set_time(1000);
+
+        // 1 day, 1 hour and 1 minute later
+        huc3.set_time(1000 + (24 * 60 + 61) * 60);
+        command(&mut huc3, 0x60);
+
+        // Read the 6 nibbles from address 0
+        command(&mut huc3, 0x40);
+        command(&mut huc3, 0x50);
+        let time: Vec<u8> = (0..6).map(|_| command(&mut huc3, 0x10)).collect();
+        assert_eq!(time, [0xD, 0x3, 0x0, 0x1, 0x0, 0x0]);
+
+        // Set the time to 2 minutes
+        command(&mut huc3, 0x40);
+        for nibble in [0x2, 0x0, 0x0, 0x0, 0x0, 0x0] {
+            command(&mut huc3, 0x30 | nibble);
+        }
+        command(&mut huc3, 0x61);
+        assert_eq!(huc3.rtc.seconds, 120);
+
+        let mut loaded = HuC3::new();
+        assert!(loaded.load_footer(&huc3.save_footer()));
+        assert_eq!(loaded.rtc.seconds, 120);
+
+        // Enable the speaker
+        assert!(huc3.audio_output(0).is_none());
+        command(&mut huc3, 0x46);
+        command(&mut huc3, 0x52);
+        command(&mut huc3, 0x31);
+        command(&mut huc3, 0x6E);
+        assert!(huc3.audio_output(0).is_some());
+    }
+}
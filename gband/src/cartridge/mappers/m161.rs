@@ -0,0 +1,61 @@
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Unlicensed mapper of the Mani 4 in 1 compilations.
+/// A single write selects the 32 KiB game mapped over the whole ROM range, until the next reset.
+pub struct M161 {
+    rom_bank_number: u8,
+    locked: bool,
+}
+
+impl M161 {
+    pub fn new() -> Self {
+        Self {
+            rom_bank_number: 0,
+            locked: false,
+        }
+    }
+}
+
+impl Mapper for M161 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x7FFF => {
+                let mask = 0x7FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 15usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if let 0x4000..=0x7FFF = addr {
+            if !self.locked {
+                self.rom_bank_number = data & 0x07;
+                self.locked = true;
+            }
+        }
+
+        None
+    }
+}
+
+impl SaveState for M161 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank_number);
+        writer.write_bool(self.locked);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank_number = reader.read_u8()?;
+        self.locked = reader.read_bool()?;
+        Ok(())
+    }
+}
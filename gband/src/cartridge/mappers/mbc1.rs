@@ -1,7 +1,13 @@
+use alloc::vec::Vec;
+
 use super::Mapper;
 use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Mbc1 {
+    /// Multicarts (MBC1M) only wire 4 bits of the ROM bank number,
+    /// so the 2 upper bits select one of the games
+    multicart: bool,
     n_rom_banks: usize,
     n_ram_banks: usize,
     bank_mask: usize,
@@ -12,20 +18,36 @@ pub struct Mbc1 {
 }
 
 impl Mbc1 {
-    pub fn new(n_rom_banks: usize, n_ram_banks: usize) -> Self {
+    pub fn new(n_rom_banks: usize, n_ram_banks: usize, multicart: bool) -> Self {
         let bank_mask = n_rom_banks - 1;
         Self {
+            multicart,
             n_rom_banks,
             n_ram_banks,
             bank_mask,
             ..Default::default()
         }
     }
+
+    /// Uses the 2 upper bits to bank on large cartridges and multicarts
+    fn has_upper_rom_bank(&self) -> bool {
+        self.multicart || self.n_rom_banks > 64
+    }
+
+    /// Number of bits of the low ROM bank register wired to the ROM
+    fn rom_bank_bits(&self) -> usize {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
 }
 
 impl Default for Mbc1 {
     fn default() -> Self {
         Self {
+            multicart: false,
             n_rom_banks: 0,
             n_ram_banks: 0,
             bank_mask: 0,
@@ -47,9 +69,10 @@ impl Mapper for Mbc1 {
                 let mask = 0x3fff;
                 let addr = (addr & mask) as usize;
 
-                if self.n_rom_banks > 64 && self.banking_mode_select {
+                if self.has_upper_rom_bank() && self.banking_mode_select {
                     // Banking using the 2 higher bits
-                    let bank = (self.ram_bank_number_or_upper_rom_bank as usize) << 19usize;
+                    let bank = (self.ram_bank_number_or_upper_rom_bank as usize)
+                        << (14 + self.rom_bank_bits());
                     CartridgeReadTarget::Rom(bank | addr)
                 } else {
                     // Not banking
@@ -62,10 +85,12 @@ impl Mapper for Mbc1 {
                 let addr = (addr & mask) as usize;
 
                 // ROM banking
-                let mut bank = (self.rom_bank_number as usize) << 14usize;
-                if self.n_rom_banks > 64 {
+                let low_bank = self.rom_bank_number as usize & ((1 << self.rom_bank_bits()) - 1);
+                let mut bank = low_bank << 14usize;
+                if self.has_upper_rom_bank() {
                     // Large ROM, using the additionnal bits
-                    bank |= (self.ram_bank_number_or_upper_rom_bank as usize) << 19usize;
+                    bank |= (self.ram_bank_number_or_upper_rom_bank as usize)
+                        << (14 + self.rom_bank_bits());
                 };
 
                 // Ram is disabled, so don't write to it
@@ -106,7 +131,12 @@ impl Mapper for Mbc1 {
             0x2000..=0x3FFF => {
                 // Set ROM Bank Number
                 // Used to bank switch range 0x4000 - 0x7FFF
-                let bank_number = data & (self.bank_mask as u8) & 0x1F;
+                // On multicarts, the bit that isn't wired still counts in the check for 0
+                let bank_number = if self.multicart {
+                    data & 0x1F
+                } else {
+                    data & (self.bank_mask as u8) & 0x1F
+                };
 
                 if bank_number == 0 {
                     // This register cannot be 0 and default to 1 if we try to set it to 0
@@ -153,4 +183,30 @@ impl Mapper for Mbc1 {
             }
         }
     }
+
+    fn registers(&self) -> Vec<(&'static str, u32)> {
+        alloc::vec![
+            ("ram_enable", self.ram_enable as u32),
+            ("rom_bank", self.rom_bank_number as u32),
+            ("ram_bank", self.ram_bank_number_or_upper_rom_bank as u32),
+            ("banking_mode", self.banking_mode_select as u32),
+        ]
+    }
+}
+
+impl SaveState for Mbc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number_or_upper_rom_bank);
+        writer.write_bool(self.banking_mode_select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number_or_upper_rom_bank = reader.read_u8()?;
+        self.banking_mode_select = reader.read_bool()?;
+        Ok(())
+    }
 }
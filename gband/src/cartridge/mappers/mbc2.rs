@@ -1,5 +1,8 @@
+use alloc::vec::Vec;
+
 use super::Mapper;
 use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Mbc2 {
     bank_mask: usize,
@@ -100,4 +103,24 @@ impl Mapper for Mbc2 {
             }
         }
     }
+
+    fn registers(&self) -> Vec<(&'static str, u32)> {
+        alloc::vec![
+            ("ram_enable", self.ram_enable as u32),
+            ("rom_bank", self.rom_bank_number as u32),
+        ]
+    }
+}
+
+impl SaveState for Mbc2 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        Ok(())
+    }
 }
@@ -1,26 +1,258 @@
+use alloc::vec::Vec;
+
 use super::Mapper;
 use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Size of the RTC footer used by most emulators, with a 64 bits timestamp
+const FOOTER_LEN: usize = 48;
+
+/// Older variant of the footer, with a 32 bits timestamp
+const FOOTER_LEN_32: usize = 44;
+
+#[derive(Default, Clone, Copy)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    day_carry: bool,
+}
+
+impl RtcRegisters {
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            0x0C => self.read_days_high(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u8, data: u8) {
+        match register {
+            0x08 => self.seconds = data & 0x3F,
+            0x09 => self.minutes = data & 0x3F,
+            0x0A => self.hours = data & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | data as u16,
+            0x0C => self.write_days_high(data),
+            _ => {}
+        }
+    }
+
+    fn read_days_high(&self) -> u8 {
+        ((self.day_carry as u8) << 7) | ((self.halt as u8) << 6) | (self.days >> 8) as u8
+    }
+
+    fn write_days_high(&mut self, data: u8) {
+        self.days = (self.days & 0xFF) | ((data as u16 & 0x01) << 8);
+        self.halt = data & 0x40 != 0;
+        self.day_carry = data & 0x80 != 0;
+    }
+
+    fn is_valid(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    fn tick(&mut self) {
+        // Out of range values count up to the width of the register, then wrap without carrying
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days = (self.days + 1) & 0x1FF;
+        if self.days == 0 {
+            self.day_carry = true;
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        if self.halt {
+            return;
+        }
+
+        // Invalid values can't be added at once, tick until they wrap around
+        let mut seconds = seconds;
+        while seconds > 0 && !self.is_valid() {
+            self.tick();
+            seconds -= 1;
+        }
+
+        if seconds == 0 {
+            return;
+        }
+
+        let total = self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400
+            + seconds;
+
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    fn write_footer(&self, footer: &mut Vec<u8>) {
+        for value in [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.read_days_high(),
+        ] {
+            footer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+    }
+
+    fn read_footer(footer: &[u8]) -> Self {
+        let value = |index: usize| footer[index * 4];
+
+        let mut registers = Self::default();
+        for (index, register) in (0x08..=0x0C).enumerate() {
+            registers.write(register, value(index));
+        }
+        registers
+    }
+}
+
+impl SaveState for RtcRegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.seconds);
+        writer.write_u8(self.minutes);
+        writer.write_u8(self.hours);
+        writer.write_u16(self.days);
+        writer.write_bool(self.halt);
+        writer.write_bool(self.day_carry);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.seconds = reader.read_u8()?;
+        self.minutes = reader.read_u8()?;
+        self.hours = reader.read_u8()?;
+        self.days = reader.read_u16()?;
+        self.halt = reader.read_bool()?;
+        self.day_carry = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Rtc {
+    registers: RtcRegisters,
+    latched: RtcRegisters,
+    latch_armed: bool,
+
+    /// UNIX time matching the registers
+    timestamp: Option<u64>,
+}
+
+impl Rtc {
+    fn write_latch(&mut self, data: u8) {
+        // Writing 0x00 then 0x01 copies the current time to the readable registers
+        if self.latch_armed && data == 0x01 {
+            self.latched = self.registers;
+        }
+
+        self.latch_armed = data == 0x00;
+    }
+
+    fn set_time(&mut self, now: u64) {
+        // Time going backwards, like with a new time source, doesn't move the clock back
+        if let Some(timestamp) = self.timestamp {
+            if now > timestamp {
+                self.registers.advance(now - timestamp);
+            }
+        }
+
+        self.timestamp = Some(now);
+    }
+}
+
+impl SaveState for Rtc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        self.latched.save_state(writer);
+        writer.write_bool(self.latch_armed);
+        writer.write_u64(self.timestamp.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.registers.load_state(reader)?;
+        self.latched.load_state(reader)?;
+        self.latch_armed = reader.read_bool()?;
+
+        // Version 6 counted the time in cycles
+        if reader.version() == 6 {
+            reader.read_u32()?;
+        }
+
+        self.timestamp = match reader.read_u64()? {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+        Ok(())
+    }
+}
 
 pub struct Mbc3 {
+    /// The MBC30 has a 8 bits ROM bank register and 8 RAM banks
+    mbc30: bool,
     ram_rtc_enable: bool,
     ram_or_rtc_bank_number: u8,
     rom_bank_number: u8,
+    rtc: Option<Rtc>,
 }
 
 impl Mbc3 {
-    pub fn new() -> Self {
+    pub fn new(has_rtc: bool, mbc30: bool) -> Self {
         Self {
+            mbc30,
+            rtc: if has_rtc { Some(Rtc::default()) } else { None },
             ..Default::default()
         }
     }
+
+    fn max_ram_bank(&self) -> u8 {
+        if self.mbc30 {
+            0x07
+        } else {
+            0x03
+        }
+    }
 }
 
 impl Default for Mbc3 {
     fn default() -> Self {
         Self {
+            mbc30: false,
             ram_or_rtc_bank_number: 0x00,
             ram_rtc_enable: false,
             rom_bank_number: 0x01,
+            rtc: None,
         }
     }
 }
@@ -44,15 +276,22 @@ impl Mapper for Mbc3 {
             }
             0xA000..=0xBFFF => {
                 // RAM or RTC range
-                // Only RAM supported at the moment
-                if self.ram_rtc_enable {
-                    let mask = 0x1FFF;
-                    let addr = (addr & mask) as usize;
+                if !self.ram_rtc_enable {
+                    return CartridgeReadTarget::Error;
+                }
 
-                    let bank = (self.ram_or_rtc_bank_number as usize) << 13usize;
-                    CartridgeReadTarget::Ram(bank | addr)
-                } else {
-                    CartridgeReadTarget::Error
+                match (self.ram_or_rtc_bank_number, &self.rtc) {
+                    (bank, _) if bank <= self.max_ram_bank() => {
+                        let mask = 0x1FFF;
+                        let addr = (addr & mask) as usize;
+
+                        let bank = (self.ram_or_rtc_bank_number as usize) << 13usize;
+                        CartridgeReadTarget::Ram(bank | addr)
+                    }
+                    (register @ 0x08..=0x0C, Some(rtc)) => {
+                        CartridgeReadTarget::Value(rtc.latched.read(register))
+                    }
+                    _ => CartridgeReadTarget::Error,
                 }
             }
             _ => {
@@ -71,7 +310,7 @@ impl Mapper for Mbc3 {
             }
             0x2000..=0x3FFF => {
                 // ROM bank number
-                let bank_number = data & 0x7F;
+                let bank_number = if self.mbc30 { data } else { data & 0x7F };
                 if bank_number == 0 {
                     self.rom_bank_number = 0x01;
                 } else {
@@ -81,30 +320,40 @@ impl Mapper for Mbc3 {
             }
             0x4000..=0x5FFF => {
                 // RAM bank number OR RTC register select
-                // data 0x00-0x03 sets RAM bank
+                // data 0x00-0x03 (0x00-0x07 on MBC30) sets RAM bank
                 // data 0x08-0x0C sets RTC register
-
-                // Temporary mask while RTC isn't implemented
-                self.ram_or_rtc_bank_number = data & 0x03;
+                self.ram_or_rtc_bank_number = data & 0x0F;
                 None
             }
             0x6000..=0x7FFF => {
                 // Latch lock data
                 // Writing 0x00 then 0x01 will write the current time to the RTC register
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(data);
+                }
                 None
             }
             0xA000..=0xBFFF => {
                 // RAM and RTC range
+                if !self.ram_rtc_enable {
+                    // RAM and RTC are disabled, nothing to do
+                    return None;
+                }
 
-                if self.ram_rtc_enable {
-                    let mask = 0x1FFF;
-                    let addr = (addr & mask) as usize;
+                let max_ram_bank = self.max_ram_bank();
+                match (self.ram_or_rtc_bank_number, &mut self.rtc) {
+                    (bank, _) if bank <= max_ram_bank => {
+                        let mask = 0x1FFF;
+                        let addr = (addr & mask) as usize;
 
-                    let bank = (self.ram_or_rtc_bank_number as usize) << 13usize;
-                    Some(bank | addr)
-                } else {
-                    // RAM and RTC are disabled, nothing to do
-                    None
+                        let bank = (self.ram_or_rtc_bank_number as usize) << 13usize;
+                        Some(bank | addr)
+                    }
+                    (register @ 0x08..=0x0C, Some(rtc)) => {
+                        rtc.registers.write(register, data);
+                        None
+                    }
+                    _ => None,
                 }
             }
             _ => {
@@ -113,4 +362,162 @@ impl Mapper for Mbc3 {
             }
         }
     }
+
+    fn set_time(&mut self, now: u64) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_time(now);
+        }
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        let mut footer = Vec::new();
+
+        if let Some(rtc) = &self.rtc {
+            rtc.registers.write_footer(&mut footer);
+            rtc.latched.write_footer(&mut footer);
+            footer.extend_from_slice(&rtc.timestamp.unwrap_or_default().to_le_bytes());
+        }
+
+        footer
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) -> bool {
+        let rtc = match &mut self.rtc {
+            Some(rtc) => rtc,
+            None => return false,
+        };
+
+        let timestamp = match footer.len() {
+            FOOTER_LEN => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            FOOTER_LEN_32 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return false,
+        };
+
+        rtc.registers = RtcRegisters::read_footer(&footer[0..20]);
+        rtc.latched = RtcRegisters::read_footer(&footer[20..40]);
+        rtc.timestamp = match timestamp {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+        true
+    }
+
+    fn registers(&self) -> Vec<(&'static str, u32)> {
+        alloc::vec![
+            ("ram_rtc_enable", self.ram_rtc_enable as u32),
+            ("rom_bank", self.rom_bank_number as u32),
+            ("ram_rtc_bank", self.ram_or_rtc_bank_number as u32),
+        ]
+    }
+}
+
+impl SaveState for Mbc3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_rtc_enable);
+        writer.write_u8(self.ram_or_rtc_bank_number);
+        writer.write_u8(self.rom_bank_number);
+
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_rtc_enable = reader.read_bool()?;
+        self.ram_or_rtc_bank_number = reader.read_u8()?;
+        self.rom_bank_number = reader.read_u8()?;
+
+        // Versions before 6 had no RTC, leave it running as-is
+        if let Some(rtc) = &mut self.rtc {
+            if reader.version() >= 6 {
+                rtc.load_state(reader)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_rtc(mbc: &mut Mbc3, register: u8) -> u8 {
+        mbc.map_write(0x4000, register);
+        match mbc.map_read(0xA000) {
+            CartridgeReadTarget::Value(value) => value,
+            _ => panic!("RTC register not mapped"),
+        }
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.map_write(0x6000, 0x00);
+        mbc.map_write(0x6000, 0x01);
+    }
+
+    #[test]
+    fn test_rtc_latch_and_tick() {
+        let mut mbc = Mbc3::new(true, false);
+        mbc.map_write(0x0000, 0x0A);
+        mbc.set_time(1000);
+
+        // Set the clock to 23:59:59 on day 0x1FF
+        for (register, value) in [
+            (0x08, 59),
+            (0x09, 59),
+            (0x0A, 23),
+            (0x0B, 0xFF),
+            (0x0C, 0x01),
+        ] {
+            mbc.map_write(0x4000, register);
+            mbc.map_write(0xA000, value);
+        }
+
+        latch(&mut mbc);
+        mbc.set_time(1001);
+
+        // The latched registers don't move until the next latch
+        assert_eq!(read_rtc(&mut mbc, 0x08), 59);
+
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 0);
+        assert_eq!(read_rtc(&mut mbc, 0x09), 0);
+        assert_eq!(read_rtc(&mut mbc, 0x0A), 0);
+        assert_eq!(read_rtc(&mut mbc, 0x0B), 0);
+        assert_eq!(read_rtc(&mut mbc, 0x0C), 0x80);
+
+        // Halted clocks don't count
+        mbc.map_write(0x4000, 0x0C);
+        mbc.map_write(0xA000, 0x40);
+        mbc.set_time(1003);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 0);
+    }
+
+    #[test]
+    fn test_rtc_footer() {
+        let mut mbc = Mbc3::new(true, false);
+        mbc.map_write(0x0000, 0x0A);
+        mbc.map_write(0x4000, 0x09);
+        mbc.map_write(0xA000, 30);
+        mbc.set_time(1_000_000);
+
+        let footer = mbc.save_footer();
+        assert_eq!(footer.len(), FOOTER_LEN);
+        assert_eq!(footer[4], 30);
+
+        // Catch up on 1 day, 1 hour and 1 minute
+        let mut loaded = Mbc3::new(true, false);
+        assert!(loaded.load_footer(&footer));
+        loaded.set_time(1_000_000 + 86400 + 3600 + 60);
+        loaded.map_write(0x0000, 0x0A);
+        latch(&mut loaded);
+
+        assert_eq!(read_rtc(&mut loaded, 0x09), 31);
+        assert_eq!(read_rtc(&mut loaded, 0x0A), 1);
+        assert_eq!(read_rtc(&mut loaded, 0x0B), 1);
+
+        assert!(!Mbc3::new(false, false).load_footer(&footer));
+        assert!(!loaded.load_footer(&footer[..40]));
+    }
 }
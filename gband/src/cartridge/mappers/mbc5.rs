@@ -1,16 +1,26 @@
+use alloc::vec::Vec;
+
 use super::Mapper;
 use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct Mbc5 {
     ram_enable: bool,
     rom_bank_number: u8,
     rom_bank_number_9th: u8,
     ram_bank_number: u8,
+
+    /// Bit 3 of the RAM bank drives a motor instead of selecting a bank
+    has_rumble: bool,
+
+    /// The motor is refreshed by the game every frame, so it isn't part of save states
+    rumble: bool,
 }
 
 impl Mbc5 {
-    pub fn new() -> Self {
+    pub fn new(has_rumble: bool) -> Self {
         Self {
+            has_rumble,
             ..Default::default()
         }
     }
@@ -23,6 +33,8 @@ impl Default for Mbc5 {
             rom_bank_number: 0x00,
             rom_bank_number_9th: 0x00,
             ram_bank_number: 0x00,
+            has_rumble: false,
+            rumble: false,
         }
     }
 }
@@ -85,7 +97,12 @@ impl Mapper for Mbc5 {
             }
             0x4000..=0x5FFF => {
                 // Set RAM bank number
-                self.ram_bank_number = data & 0xF;
+                if self.has_rumble {
+                    self.rumble = data & 0x08 != 0;
+                    self.ram_bank_number = data & 0x07;
+                } else {
+                    self.ram_bank_number = data & 0x0F;
+                }
                 None
             }
             0xA000..=0xBFFF => {
@@ -108,4 +125,35 @@ impl Mapper for Mbc5 {
             }
         }
     }
+
+    fn registers(&self) -> Vec<(&'static str, u32)> {
+        alloc::vec![
+            ("ram_enable", self.ram_enable as u32),
+            ("rom_bank", self.rom_bank_number as u32),
+            ("rom_bank_9th", self.rom_bank_number_9th as u32),
+            ("ram_bank", self.ram_bank_number as u32),
+            ("rumble", self.rumble as u32),
+        ]
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+impl SaveState for Mbc5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.rom_bank_number_9th);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.rom_bank_number_9th = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,345 @@
+use alloc::vec::Vec;
+
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// The MBC6 carts have 1 MiB of flash memory, which is saved after the RAM
+const FLASH_SIZE: usize = 0x100000;
+
+/// Flash is erased by sectors of 128 KiB
+const FLASH_SECTOR_SIZE: usize = 0x20000;
+
+/// Manufacturer and device ID of the Macronix flash chip
+const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+/// Progress in the command sequences of the flash chip
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum FlashCommand {
+    #[default]
+    Ready,
+    Unlock1,
+    Unlock2,
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+    Program,
+    Id,
+}
+
+impl FlashCommand {
+    fn from_u8(value: u8) -> Result<Self, SaveStateError> {
+        Ok(match value {
+            0 => Self::Ready,
+            1 => Self::Unlock1,
+            2 => Self::Unlock2,
+            3 => Self::Erase,
+            4 => Self::EraseUnlock1,
+            5 => Self::EraseUnlock2,
+            6 => Self::Program,
+            7 => Self::Id,
+            _ => return Err(SaveStateError::InvalidData),
+        })
+    }
+}
+
+struct Flash {
+    data: Vec<u8>,
+    command: FlashCommand,
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self {
+            // Erased flash reads as 0xFF
+            data: alloc::vec![0xFF; FLASH_SIZE],
+            command: Default::default(),
+        }
+    }
+}
+
+impl Flash {
+    fn read(&self, addr: usize) -> u8 {
+        match self.command {
+            FlashCommand::Id => FLASH_ID[addr & 1],
+            _ => self.data[addr % FLASH_SIZE],
+        }
+    }
+
+    fn write(&mut self, addr: usize, data: u8) {
+        // Commands are decoded from the lower address bits
+        let command_addr = addr & 0x7FFF;
+
+        self.command = match (self.command, command_addr, data) {
+            (FlashCommand::Program, _, _) => {
+                // Programming can only clear bits, erasing sets them back
+                self.data[addr % FLASH_SIZE] &= data;
+                FlashCommand::Ready
+            }
+            // Reset can be written at any time, except as data to program
+            (_, _, 0xF0) => FlashCommand::Ready,
+            (FlashCommand::Ready, 0x5555, 0xAA) => FlashCommand::Unlock1,
+            (FlashCommand::Unlock1, 0x2AAA, 0x55) => FlashCommand::Unlock2,
+            (FlashCommand::Unlock2, 0x5555, 0x80) => FlashCommand::Erase,
+            (FlashCommand::Unlock2, 0x5555, 0x90) => FlashCommand::Id,
+            (FlashCommand::Unlock2, 0x5555, 0xA0) => FlashCommand::Program,
+            (FlashCommand::Erase, 0x5555, 0xAA) => FlashCommand::EraseUnlock1,
+            (FlashCommand::EraseUnlock1, 0x2AAA, 0x55) => FlashCommand::EraseUnlock2,
+            (FlashCommand::EraseUnlock2, 0x5555, 0x10) => {
+                // Chip erase
+                self.data.fill(0xFF);
+                FlashCommand::Ready
+            }
+            (FlashCommand::EraseUnlock2, _, 0x30) => {
+                // Sector erase
+                let start = (addr % FLASH_SIZE) & !(FLASH_SECTOR_SIZE - 1);
+                self.data[start..start + FLASH_SECTOR_SIZE].fill(0xFF);
+                FlashCommand::Ready
+            }
+            (FlashCommand::Id, _, _) => FlashCommand::Id,
+            _ => FlashCommand::Ready,
+        };
+    }
+}
+
+/// One of the two 8 KiB windows in 0x4000-0x7FFF, mapping either ROM or flash
+#[derive(Default, Clone, Copy)]
+struct RomWindow {
+    bank: u8,
+    flash: bool,
+}
+
+impl RomWindow {
+    fn addr(&self, addr: u16) -> usize {
+        ((self.bank as usize) << 13usize) | (addr & 0x1FFF) as usize
+    }
+}
+
+#[derive(Default)]
+pub struct Mbc6 {
+    ram_enable: bool,
+
+    /// RAM is mapped in 2 windows of 4 KiB, at 0xA000 and 0xB000
+    ram_banks: [u8; 2],
+    rom_windows: [RomWindow; 2],
+
+    flash_enable: bool,
+    flash_write_enable: bool,
+    flash: Flash,
+}
+
+impl Mbc6 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        let bank = self.ram_banks[((addr >> 12) & 1) as usize] as usize;
+        (bank << 12usize) | (addr & 0x0FFF) as usize
+    }
+}
+
+impl Mapper for Mbc6 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable ROM or flash banks
+                let window = self.rom_windows[((addr >> 13) & 1) as usize];
+
+                if window.flash {
+                    CartridgeReadTarget::Value(self.flash.read(window.addr(addr)))
+                } else {
+                    CartridgeReadTarget::Rom(window.addr(addr))
+                }
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can only be used when enabled
+                if self.ram_enable {
+                    CartridgeReadTarget::Ram(self.ram_addr(addr))
+                } else {
+                    CartridgeReadTarget::Error
+                }
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x03FF => {
+                // Enables or disables the RAM
+                self.ram_enable = data & 0xF == 0x0A;
+                None
+            }
+            0x0400..=0x0BFF => {
+                // RAM bank of the window at 0xA000 or 0xB000
+                self.ram_banks[((addr >> 11) & 1) as usize] = data;
+                None
+            }
+            0x0C00..=0x0FFF => {
+                // Flash enable, only writable while flash writes are enabled
+                if self.flash_write_enable {
+                    self.flash_enable = data & 1 == 1;
+                }
+                None
+            }
+            0x1000 => {
+                // Flash write enable
+                self.flash_write_enable = data & 1 == 1;
+                None
+            }
+            0x2000..=0x3FFF => {
+                // ROM or flash bank number, and selection between ROM and flash,
+                //  for the windows at 0x4000 and 0x6000
+                let window = &mut self.rom_windows[((addr >> 12) & 1) as usize];
+
+                if addr & 0x0800 == 0 {
+                    window.bank = data & 0x7F;
+                } else {
+                    window.flash = data == 0x08;
+                }
+                None
+            }
+            0x4000..=0x7FFF => {
+                // Flash commands
+                let window = self.rom_windows[((addr >> 13) & 1) as usize];
+
+                if window.flash && self.flash_enable && self.flash_write_enable {
+                    self.flash.write(window.addr(addr), data);
+                }
+                None
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can only be used when enabled
+                if self.ram_enable {
+                    Some(self.ram_addr(addr))
+                } else {
+                    // Ram is disabled, so don't write to it
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        self.flash.data.clone()
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FLASH_SIZE {
+            return false;
+        }
+
+        self.flash.data.copy_from_slice(footer);
+        true
+    }
+}
+
+impl SaveState for Mbc6 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_bytes(&self.ram_banks);
+
+        for window in &self.rom_windows {
+            writer.write_u8(window.bank);
+            writer.write_bool(window.flash);
+        }
+
+        writer.write_bool(self.flash_enable);
+        writer.write_bool(self.flash_write_enable);
+        writer.write_u8(self.flash.command as u8);
+        writer.write_slice(&self.flash.data);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable = reader.read_bool()?;
+        reader.read_bytes(&mut self.ram_banks)?;
+
+        for window in &mut self.rom_windows {
+            window.bank = reader.read_u8()?;
+            window.flash = reader.read_bool()?;
+        }
+
+        self.flash_enable = reader.read_bool()?;
+        self.flash_write_enable = reader.read_bool()?;
+        self.flash.command = FlashCommand::from_u8(reader.read_u8()?)?;
+        reader.read_slice(&mut self.flash.data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a command to the flash through the window at 0x4000, mapped to flash bank 2
+    fn command(mbc: &mut Mbc6, command: u8) {
+        mbc.map_write(0x2000, 0x02);
+        mbc.map_write(0x5555, 0xAA);
+        mbc.map_write(0x2000, 0x01);
+        mbc.map_write(0x4AAA, 0x55);
+        mbc.map_write(0x2000, 0x02);
+        mbc.map_write(0x5555, command);
+    }
+
+    fn read(mbc: &Mbc6, addr: u16) -> u8 {
+        match mbc.map_read(addr) {
+            CartridgeReadTarget::Value(value) => value,
+            _ => panic!("Flash isn't mapped"),
+        }
+    }
+
+    #[test]
+    fn test_flash_commands() {
+        let mut mbc = Mbc6::new();
+        mbc.map_write(0x1000, 0x01);
+        mbc.map_write(0x0C00, 0x01);
+        mbc.map_write(0x2800, 0x08);
+
+        command(&mut mbc, 0x90);
+        assert_eq!(read(&mbc, 0x4000), 0xC2);
+        assert_eq!(read(&mbc, 0x4001), 0x81);
+        mbc.map_write(0x4000, 0xF0);
+
+        // Program a byte in bank 5
+        command(&mut mbc, 0xA0);
+        mbc.map_write(0x2000, 0x05);
+        mbc.map_write(0x4123, 0x5A);
+        assert_eq!(read(&mbc, 0x4123), 0x5A);
+
+        // Programming can't set bits back
+        command(&mut mbc, 0xA0);
+        mbc.map_write(0x2000, 0x05);
+        mbc.map_write(0x4123, 0xF0);
+        assert_eq!(read(&mbc, 0x4123), 0x50);
+
+        let mut loaded = Mbc6::new();
+        assert!(loaded.load_footer(&mbc.save_footer()));
+        assert_eq!(loaded.flash.data[0xA123], 0x50);
+
+        // Erase the first sector
+        command(&mut mbc, 0x80);
+        command(&mut mbc, 0x30);
+        mbc.map_write(0x2000, 0x05);
+        assert_eq!(read(&mbc, 0x4123), 0xFF);
+
+        // Without write enable, commands are ignored
+        mbc.map_write(0x1000, 0x00);
+        command(&mut mbc, 0x90);
+        assert_eq!(read(&mbc, 0x4000), 0xFF);
+    }
+}
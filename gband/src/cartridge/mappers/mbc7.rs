@@ -0,0 +1,490 @@
+use alloc::vec::Vec;
+
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Value of the accelerometer when the cartridge is flat
+const TILT_CENTER: u16 = 0x81D0;
+
+/// Change of the accelerometer value for 1G
+const TILT_GRAVITY: f32 = 0x70 as f32;
+
+/// Value of the accelerometer registers after erasing the latch
+const TILT_ERASED: u16 = 0x8000;
+
+/// The 93LC56 holds 128 words of 16 bits
+const EEPROM_WORDS: usize = 128;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum EepromState {
+    /// Waiting for a start bit
+    #[default]
+    Idle,
+    /// Receiving the opcode and the address
+    Command,
+    /// Shifting out a word
+    Read,
+    /// Receiving a word to write at an address
+    Write(u8),
+    /// Receiving a word to write everywhere
+    WriteAll,
+}
+
+impl EepromState {
+    fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Self::Idle => [0, 0],
+            Self::Command => [1, 0],
+            Self::Read => [2, 0],
+            Self::Write(addr) => [3, addr],
+            Self::WriteAll => [4, 0],
+        }
+    }
+
+    /// Also checks the number of bits shifted in or out, which is only valid in some ranges for each state
+    fn from_bytes(value: [u8; 2], bits: u8) -> Result<Self, SaveStateError> {
+        let (state, valid) = match value {
+            [0, _] => (Self::Idle, true),
+            [1, _] => (Self::Command, bits < 10),
+            [2, _] => (Self::Read, (1..=16).contains(&bits)),
+            [3, addr] => (
+                Self::Write(addr),
+                (addr as usize) < EEPROM_WORDS && bits < 16,
+            ),
+            [4, _] => (Self::WriteAll, bits < 16),
+            _ => return Err(SaveStateError::InvalidData),
+        };
+
+        if valid {
+            Ok(state)
+        } else {
+            Err(SaveStateError::InvalidData)
+        }
+    }
+}
+
+/// 93LC56 serial EEPROM, driven by bit-banging a register
+struct Eeprom {
+    data: [u16; EEPROM_WORDS],
+    write_enable: bool,
+
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+
+    state: EepromState,
+    shift: u16,
+    bits: u8,
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self {
+            data: [0xFFFF; EEPROM_WORDS],
+            write_enable: false,
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            state: Default::default(),
+            shift: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl Eeprom {
+    fn read(&self) -> u8 {
+        ((self.chip_select as u8) << 7)
+            | ((self.clock as u8) << 6)
+            | ((self.data_in as u8) << 1)
+            | self.data_out as u8
+    }
+
+    fn write(&mut self, data: u8) {
+        let chip_select = data & 0x80 != 0;
+        let clock = data & 0x40 != 0;
+        let data_in = data & 0x02 != 0;
+
+        if !chip_select {
+            self.state = EepromState::Idle;
+        } else if clock && !self.clock {
+            // Bits are shifted on rising edges
+            self.clock_bit(data_in);
+        }
+
+        self.chip_select = chip_select;
+        self.clock = clock;
+        self.data_in = data_in;
+    }
+
+    fn clock_bit(&mut self, bit: bool) {
+        match self.state {
+            EepromState::Idle => {
+                if bit {
+                    self.state = EepromState::Command;
+                    self.shift = 0;
+                    self.bits = 0;
+                }
+            }
+            EepromState::Command => {
+                self.shift = (self.shift << 1) | bit as u16;
+                self.bits += 1;
+
+                // 2 bits of opcode and 8 bits of address
+                if self.bits == 10 {
+                    self.execute(self.shift);
+                }
+            }
+            EepromState::Read => {
+                self.data_out = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bits -= 1;
+
+                if self.bits == 0 {
+                    self.state = EepromState::Idle;
+                }
+            }
+            EepromState::Write(_) | EepromState::WriteAll => {
+                self.shift = (self.shift << 1) | bit as u16;
+                self.bits += 1;
+
+                if self.bits == 16 {
+                    if self.write_enable {
+                        match self.state {
+                            EepromState::Write(addr) => self.data[addr as usize] = self.shift,
+                            _ => self.data.fill(self.shift),
+                        }
+                    }
+
+                    self.state = EepromState::Idle;
+                    self.data_out = true;
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self, command: u16) {
+        // The highest bit of the address is ignored in 16 bits mode
+        let addr = (command & 0x7F) as u8;
+        self.shift = 0;
+        self.bits = 0;
+        self.state = EepromState::Idle;
+
+        match (command >> 8) & 0b11 {
+            0b10 => {
+                // READ, starting with a dummy 0 bit
+                self.shift = self.data[addr as usize];
+                self.bits = 16;
+                self.data_out = false;
+                self.state = EepromState::Read;
+            }
+            0b01 => self.state = EepromState::Write(addr),
+            0b11 => {
+                // ERASE
+                if self.write_enable {
+                    self.data[addr as usize] = 0xFFFF;
+                }
+                self.data_out = true;
+            }
+            _ => match (command >> 6) & 0b11 {
+                0b11 => self.write_enable = true,
+                0b00 => self.write_enable = false,
+                0b10 => {
+                    // ERAL
+                    if self.write_enable {
+                        self.data.fill(0xFFFF);
+                    }
+                    self.data_out = true;
+                }
+                _ => self.state = EepromState::WriteAll,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Mbc7 {
+    /// Both need to be enabled to access the registers
+    ram_enable_1: bool,
+    ram_enable_2: bool,
+    rom_bank_number: u8,
+
+    /// Current reading of the accelerometer
+    tilt: (u16, u16),
+
+    /// Values latched from the accelerometer
+    latch: (u16, u16),
+    latch_erased: bool,
+
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new() -> Self {
+        Self {
+            rom_bank_number: 0x01,
+            tilt: (TILT_CENTER, TILT_CENTER),
+            latch: (TILT_ERASED, TILT_ERASED),
+            ..Default::default()
+        }
+    }
+
+    fn registers_enabled(&self) -> bool {
+        self.ram_enable_1 && self.ram_enable_2
+    }
+}
+
+impl Mapper for Mbc7 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 14usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            0xA000..=0xAFFF if self.registers_enabled() => {
+                // Accelerometer and EEPROM registers
+                let value = match (addr >> 4) & 0xF {
+                    0x2 => self.latch.0 as u8,
+                    0x3 => (self.latch.0 >> 8) as u8,
+                    0x4 => self.latch.1 as u8,
+                    0x5 => (self.latch.1 >> 8) as u8,
+                    0x6 => 0x00,
+                    0x8 => self.eeprom.read(),
+                    _ => 0xFF,
+                };
+
+                CartridgeReadTarget::Value(value)
+            }
+            0xA000..=0xBFFF => CartridgeReadTarget::Value(0xFF),
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                // First RAM enable
+                self.ram_enable_1 = data == 0x0A;
+                None
+            }
+            0x2000..=0x3FFF => {
+                // ROM bank number
+                self.rom_bank_number = data & 0x7F;
+                None
+            }
+            0x4000..=0x5FFF => {
+                // Second RAM enable
+                self.ram_enable_2 = data == 0x40;
+                None
+            }
+            0xA000..=0xAFFF if self.registers_enabled() => {
+                match ((addr >> 4) & 0xF, data) {
+                    (0x0, 0x55) => {
+                        // Erase the latch
+                        self.latch = (TILT_ERASED, TILT_ERASED);
+                        self.latch_erased = true;
+                    }
+                    (0x1, 0xAA) if self.latch_erased => {
+                        // Latch the accelerometer
+                        self.latch = self.tilt;
+                        self.latch_erased = false;
+                    }
+                    (0x8, _) => self.eeprom.write(data),
+                    _ => {}
+                }
+                None
+            }
+            0x6000..=0x7FFF | 0xA000..=0xBFFF => None,
+            _ => {
+                log::warn!("Write on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                None
+            }
+        }
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        // The accelerometer value goes down when tilting right, and up when tilting towards the player
+        let x = TILT_CENTER as f32 - x.clamp(-1.0, 1.0) * TILT_GRAVITY;
+        let y = TILT_CENTER as f32 + y.clamp(-1.0, 1.0) * TILT_GRAVITY;
+        self.tilt = (x as u16, y as u16);
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        self.eeprom
+            .data
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != EEPROM_WORDS * 2 {
+            return false;
+        }
+
+        for (word, bytes) in self.eeprom.data.iter_mut().zip(footer.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        true
+    }
+}
+
+impl SaveState for Mbc7 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable_1);
+        writer.write_bool(self.ram_enable_2);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u16(self.latch.0);
+        writer.write_u16(self.latch.1);
+        writer.write_bool(self.latch_erased);
+
+        let eeprom = &self.eeprom;
+        writer.write_bytes(&self.save_footer());
+        writer.write_bool(eeprom.write_enable);
+        writer.write_bool(eeprom.chip_select);
+        writer.write_bool(eeprom.clock);
+        writer.write_bool(eeprom.data_in);
+        writer.write_bool(eeprom.data_out);
+        writer.write_bytes(&eeprom.state.to_bytes());
+        writer.write_u16(eeprom.shift);
+        writer.write_u8(eeprom.bits);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable_1 = reader.read_bool()?;
+        self.ram_enable_2 = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.latch.0 = reader.read_u16()?;
+        self.latch.1 = reader.read_u16()?;
+        self.latch_erased = reader.read_bool()?;
+
+        let mut data = [0u8; EEPROM_WORDS * 2];
+        reader.read_bytes(&mut data)?;
+        self.load_footer(&data);
+
+        let eeprom = &mut self.eeprom;
+        eeprom.write_enable = reader.read_bool()?;
+        eeprom.chip_select = reader.read_bool()?;
+        eeprom.clock = reader.read_bool()?;
+        eeprom.data_in = reader.read_bool()?;
+        eeprom.data_out = reader.read_bool()?;
+
+        let mut state = [0u8; 2];
+        reader.read_bytes(&mut state)?;
+        eeprom.shift = reader.read_u16()?;
+        eeprom.bits = reader.read_u8()?;
+        eeprom.state = EepromState::from_bytes(state, eeprom.bits)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(mbc: &Mbc7, addr: u16) -> u8 {
+        match mbc.map_read(addr) {
+            CartridgeReadTarget::Value(value) => value,
+            _ => panic!("Registers aren't mapped"),
+        }
+    }
+
+    /// Clock bits into the EEPROM, returning the bits read back
+    fn send_bits(mbc: &mut Mbc7, bits: u32, count: u8) -> u32 {
+        let mut out = 0;
+
+        for i in (0..count).rev() {
+            let bit = (((bits >> i) & 1) as u8) << 1;
+            mbc.map_write(0xA080, 0x80 | bit);
+            mbc.map_write(0xA080, 0xC0 | bit);
+            out = (out << 1) | (read(mbc, 0xA080) & 1) as u32;
+        }
+
+        mbc.map_write(0xA080, 0x00);
+        out
+    }
+
+    #[test]
+    fn test_accelerometer() {
+        let mut mbc = Mbc7::new();
+        mbc.map_write(0x0000, 0x0A);
+        mbc.map_write(0x4000, 0x40);
+
+        mbc.set_tilt(0.0, 1.0);
+        mbc.map_write(0xA000, 0x55);
+        assert_eq!(read(&mbc, 0xA020), 0x00);
+        assert_eq!(read(&mbc, 0xA030), 0x80);
+
+        mbc.map_write(0xA010, 0xAA);
+        assert_eq!(read(&mbc, 0xA020), 0xD0);
+        assert_eq!(read(&mbc, 0xA030), 0x81);
+        assert_eq!(read(&mbc, 0xA040), 0x40);
+        assert_eq!(read(&mbc, 0xA050), 0x82);
+
+        // The latch needs to be erased first
+        mbc.set_tilt(0.0, 0.0);
+        mbc.map_write(0xA010, 0xAA);
+        assert_eq!(read(&mbc, 0xA040), 0x40);
+    }
+
+    #[test]
+    fn test_eeprom() {
+        let mut mbc = Mbc7::new();
+        mbc.map_write(0x0000, 0x0A);
+        mbc.map_write(0x4000, 0x40);
+
+        // Commands are a start bit, 2 bits of opcode and 8 bits of address
+        // EWEN, then WRITE 0x1234 at 0x05
+        send_bits(&mut mbc, 0x4C0, 11);
+        send_bits(&mut mbc, (0x505 << 16) | 0x1234, 27);
+
+        // READ 0x05
+        let out = send_bits(&mut mbc, 0x605 << 16, 27);
+        assert_eq!(out & 0xFFFF, 0x1234);
+
+        let footer = mbc.save_footer();
+        assert_eq!(&footer[10..12], &[0x34, 0x12]);
+
+        let mut loaded = Mbc7::new();
+        assert!(loaded.load_footer(&footer));
+        assert_eq!(loaded.eeprom.data[5], 0x1234);
+    }
+
+    #[test]
+    fn test_invalid_eeprom_state() {
+        let mut writer = StateWriter::new();
+        writer.write_header(&[]);
+        Mbc7::new().save_state(&mut writer);
+        let valid = writer.into_inner();
+
+        // The state is followed by the shift register and the number of bits, at the end
+        let state = valid.len() - 5;
+        for (bytes, bits) in [([3, 0x80], 0), ([3, 0x05], 16), ([2, 0], 0), ([1, 0], 10)] {
+            let mut data = valid.clone();
+            data[state..state + 2].copy_from_slice(&bytes);
+            data[state + 4] = bits;
+
+            let mut reader = StateReader::new(&data, &[]).unwrap();
+            assert!(matches!(
+                Mbc7::new().load_state(&mut reader),
+                Err(SaveStateError::InvalidData)
+            ));
+        }
+    }
+}
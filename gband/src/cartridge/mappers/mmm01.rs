@@ -0,0 +1,203 @@
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Mapper of multi-game compilations.
+/// It boots "unmapped", with the menu in the last 32 KiB of the ROM.
+/// The menu then configures the banks and masks of a game and maps it, which locks most registers.
+#[derive(Default)]
+pub struct Mmm01 {
+    mapped: bool,
+    ram_enable: bool,
+
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+
+    /// Bits 1-4 of the ROM bank that the game can't change, set by the menu
+    rom_bank_mask: u8,
+
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+
+    /// Bits of the RAM bank that the game can't change, set by the menu
+    ram_bank_mask: u8,
+
+    mbc1_mode: bool,
+    mbc1_mode_locked: bool,
+}
+
+impl Mmm01 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// Bits of the low ROM bank register that the game can write
+    fn rom_bank_writable(&self) -> u8 {
+        !(self.rom_bank_mask << 1) & 0x1F
+    }
+
+    /// Bits of the low RAM bank register that the game can write
+    fn ram_bank_writable(&self) -> u8 {
+        !self.ram_bank_mask & 0x03
+    }
+
+    fn rom_bank(&self, low: u8) -> usize {
+        if !self.mapped {
+            // Every bit is forced high, so the menu is at the end of the ROM
+            return 0x1FE | (low as usize & 0x01);
+        }
+
+        ((self.rom_bank_high as usize) << 7) | ((self.rom_bank_mid as usize) << 5) | low as usize
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        // Like the MBC1, the RAM bank is only used in the second mode
+        let low = if self.mbc1_mode {
+            self.ram_bank_low
+        } else {
+            self.ram_bank_low & !self.ram_bank_writable()
+        };
+
+        let bank = ((self.ram_bank_high as usize) << 2) | low as usize;
+        (bank << 13usize) | (addr & 0x1FFF) as usize
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank of the game, without the bits it can change
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = self.rom_bank(self.rom_bank_low & !self.rom_bank_writable());
+
+                CartridgeReadTarget::Rom((bank << 14usize) | addr)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+
+                // Like the MBC1, bank 0 is replaced by bank 1, but only the writable bits are checked
+                let mut low = self.rom_bank_low;
+                if low & self.rom_bank_writable() == 0 {
+                    low |= 0x01;
+                }
+
+                CartridgeReadTarget::Rom((self.rom_bank(low) << 14usize) | addr)
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can only be used when enabled
+                if self.ram_enable {
+                    CartridgeReadTarget::Ram(self.ram_addr(addr))
+                } else {
+                    CartridgeReadTarget::Error
+                }
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                // RAM enable, and in unmapped mode, the RAM bank mask and the map enable
+                self.ram_enable = data & 0x0F == 0x0A;
+
+                if !self.mapped {
+                    self.ram_bank_mask = (data >> 4) & 0b11;
+                    self.mapped = data & 0x40 != 0;
+                }
+                None
+            }
+            0x2000..=0x3FFF => {
+                // Low ROM bank, and in unmapped mode, the middle ROM bank
+                if self.mapped {
+                    let writable = self.rom_bank_writable();
+                    self.rom_bank_low = (self.rom_bank_low & !writable) | (data & writable);
+                } else {
+                    self.rom_bank_low = data & 0x1F;
+                    self.rom_bank_mid = (data >> 5) & 0b11;
+                }
+                None
+            }
+            0x4000..=0x5FFF => {
+                // Low RAM bank, and in unmapped mode, the high RAM and ROM banks
+                if self.mapped {
+                    let writable = self.ram_bank_writable();
+                    self.ram_bank_low = (self.ram_bank_low & !writable) | (data & writable);
+                } else {
+                    self.ram_bank_low = data & 0b11;
+                    self.ram_bank_high = (data >> 2) & 0b11;
+                    self.rom_bank_high = (data >> 4) & 0b11;
+                    self.mbc1_mode_locked = data & 0x40 != 0;
+                }
+                None
+            }
+            0x6000..=0x7FFF => {
+                // MBC1 banking mode, and in unmapped mode, the ROM bank mask
+                if !(self.mapped && self.mbc1_mode_locked) {
+                    self.mbc1_mode = data & 1 == 1;
+                }
+
+                if !self.mapped {
+                    self.rom_bank_mask = (data >> 2) & 0x0F;
+                }
+                None
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can only be used when enabled
+                if self.ram_enable {
+                    Some(self.ram_addr(addr))
+                } else {
+                    // Ram is disabled, so don't write to it
+                    None
+                }
+            }
+            _ => {
+                log::warn!("Write on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                None
+            }
+        }
+    }
+}
+
+impl SaveState for Mmm01 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.mapped);
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_low);
+        writer.write_u8(self.rom_bank_mid);
+        writer.write_u8(self.rom_bank_high);
+        writer.write_u8(self.rom_bank_mask);
+        writer.write_u8(self.ram_bank_low);
+        writer.write_u8(self.ram_bank_high);
+        writer.write_u8(self.ram_bank_mask);
+        writer.write_bool(self.mbc1_mode);
+        writer.write_bool(self.mbc1_mode_locked);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mapped = reader.read_bool()?;
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_low = reader.read_u8()?;
+        self.rom_bank_mid = reader.read_u8()?;
+        self.rom_bank_high = reader.read_u8()?;
+        self.rom_bank_mask = reader.read_u8()?;
+        self.ram_bank_low = reader.read_u8()?;
+        self.ram_bank_high = reader.read_u8()?;
+        self.ram_bank_mask = reader.read_u8()?;
+        self.mbc1_mode = reader.read_bool()?;
+        self.mbc1_mode_locked = reader.read_bool()?;
+        Ok(())
+    }
+}
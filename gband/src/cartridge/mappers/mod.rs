@@ -1,18 +1,95 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
 use super::CartridgeReadTarget;
+use crate::image_source::ImageSource;
+use crate::save_state::SaveState;
 
+mod huc1;
+mod huc3;
+mod m161;
 mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
 mod no_mapper;
+mod pocket_camera;
+mod sachen;
+mod tama5;
+mod wisdom_tree;
 
+pub use huc1::HuC1;
+pub use huc3::HuC3;
+pub use m161::M161;
 pub use mbc1::Mbc1;
 pub use mbc2::Mbc2;
 pub use mbc3::Mbc3;
 pub use mbc5::Mbc5;
+pub use mbc6::Mbc6;
+pub use mbc7::Mbc7;
+pub use mmm01::Mmm01;
 pub use no_mapper::NoMapper;
+pub use pocket_camera::PocketCamera;
+pub use sachen::SachenMmc1;
+pub use tama5::Tama5;
+pub use wisdom_tree::WisdomTree;
 
-pub trait Mapper: Send + Sync {
+/// Mappers are part of save states, so they need to be able to save their internal registers.
+/// Other crates can provide their own through a `MapperRegistry`.
+pub trait Mapper: SaveState + Send + Sync {
+    /// Map a read in 0x0000-0x7FFF or 0xA000-0xBFFF
     fn map_read(&self, addr: u16) -> CartridgeReadTarget;
+
+    /// Handle a write in 0x0000-0x7FFF or 0xA000-0xBFFF.
+    /// Returns the offset in RAM to write the data to, if any.
     fn map_write(&mut self, addr: u16, data: u8) -> Option<usize>;
+
+    /// Advance the hardware on the cartridge by T-cycles at ~4MHz.
+    /// `ram` is the cartridge RAM, for hardware writing to it by itself.
+    fn clock(&mut self, _cycles: u32, _ram: &mut [u8]) {}
+
+    /// Let clocks on the cartridge catch up with the current time, in seconds since the UNIX epoch
+    fn set_time(&mut self, _now: u64) {}
+
+    /// Update the accelerometer on the cartridge, in G
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    /// Replace the pictures seen by a camera on the cartridge
+    fn set_image_source(&mut self, _image_source: Box<dyn ImageSource>) {}
+
+    /// Sound made by the cartridge at a T-cycle, between -1.0 and 1.0. None when silent.
+    fn audio_output(&self, _cycle_count: u64) -> Option<f32> {
+        None
+    }
+
+    /// Whether the rumble motor on the cartridge is running
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// State of the infrared LED on the cartridge, None while its infrared port isn't mapped
+    fn ir_led(&self) -> Option<bool> {
+        None
+    }
+
+    /// Tell the infrared sensor on the cartridge whether it receives light
+    fn set_ir_light(&mut self, _light: bool) {}
+
+    /// Battery-backed state appended after the RAM in save files
+    fn save_footer(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore a footer written by `save_footer`. Returns false if the format isn't recognized.
+    fn load_footer(&mut self, _footer: &[u8]) -> bool {
+        false
+    }
+
+    /// Internal registers as names and values, for debuggers
+    fn registers(&self) -> Vec<(&'static str, u32)> {
+        Vec::new()
+    }
 }
@@ -1,5 +1,6 @@
 use super::Mapper;
 use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct NoMapper;
 
@@ -39,3 +40,12 @@ impl Mapper for NoMapper {
         }
     }
 }
+
+impl SaveState for NoMapper {
+    // No internal registers
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), SaveStateError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use alloc::boxed::Box;
+
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::image_source::{ImageSource, TestPattern, CAMERA_HEIGHT, CAMERA_WIDTH};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Number of camera registers, including the 4x4 dithering matrix
+const REGISTERS_LEN: usize = 0x36;
+
+/// The picture is written in RAM bank 0 as 16x14 tiles, after the first 256 bytes
+const IMAGE_OFFSET: usize = 0x100;
+
+/// Gain of the sensor for each value of the 5 bits of A001
+const GAINS: [f32; 32] = [
+    0.8809, 0.9149, 0.9457, 0.9740, 1.0000, 1.0241, 1.0467, 1.0677, 1.0876, 1.1240, 1.1569, 1.1868,
+    1.2143, 1.2396, 1.2744, 1.3157, 1.3525, 1.3857, 1.4158, 1.4434, 1.4690, 1.4927, 1.5148, 1.5356,
+    1.5551, 1.5736, 1.5911, 1.6077, 1.6235, 1.6387, 1.6531, 1.6670,
+];
+
+/// Edge enhancement ratio for each value of the bits 4-6 of A004
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+pub struct PocketCamera {
+    ram_enable: bool,
+    rom_bank_number: u8,
+
+    /// Bit 4 maps the camera registers instead of the RAM
+    ram_bank_number: u8,
+
+    registers: [u8; REGISTERS_LEN],
+
+    /// T-cycles left before the picture is written to RAM
+    capture_cycles: u32,
+
+    image_source: Box<dyn ImageSource>,
+}
+
+impl PocketCamera {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.ram_bank_number & 0x10 != 0
+    }
+
+    fn ram_addr(&self, addr: u16) -> usize {
+        let mask = 0x1FFF;
+        let addr = (addr & mask) as usize;
+        let bank = ((self.ram_bank_number & 0x0F) as usize) << 13usize;
+
+        bank | addr
+    }
+
+    fn start_capture(&mut self) {
+        // A base time, a longer time without the N flag, and the exposure time
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as u32;
+        let n_flag = self.registers[1] & 0x80 != 0;
+
+        self.capture_cycles = 129792 + if n_flag { 0 } else { 2048 } + exposure * 64;
+    }
+
+    /// Take a picture and write it as tiles in RAM, like the sensor and the dithering circuit
+    fn capture(&mut self, ram: &mut [u8]) {
+        let mut image = [0u8; CAMERA_WIDTH * CAMERA_HEIGHT];
+        self.image_source.capture(&mut image);
+
+        let gain = GAINS[(self.registers[1] & 0x1F) as usize];
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as f32 / 4096.0;
+        let sensor = |x: usize, y: usize| image[y * CAMERA_WIDTH + x] as f32 * gain * exposure;
+
+        // Edge enhancement is only used when both the N and VH flags are set
+        let edge_ratio = if self.registers[1] & 0xE0 == 0xE0 {
+            Some(EDGE_RATIOS[((self.registers[4] >> 4) & 0x07) as usize])
+        } else {
+            None
+        };
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let mut value = sensor(x, y);
+
+                if let Some(ratio) = edge_ratio {
+                    let neighbours = sensor(x.saturating_sub(1), y)
+                        + sensor((x + 1).min(CAMERA_WIDTH - 1), y)
+                        + sensor(x, y.saturating_sub(1))
+                        + sensor(x, (y + 1).min(CAMERA_HEIGHT - 1));
+                    value += (value * 4.0 - neighbours) * ratio;
+                }
+
+                // Each pixel of the 4x4 matrix has 3 thresholds between the 4 shades
+                let matrix = 6 + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let shade = thresholds
+                    .iter()
+                    .filter(|threshold| value < **threshold as f32)
+                    .count() as u8;
+
+                let offset = IMAGE_OFFSET + ((y / 8) * 16 + x / 8) * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                if let Some(tile_row) = ram.get_mut(offset..offset + 2) {
+                    tile_row[0] = (tile_row[0] & !(1 << bit)) | ((shade & 1) << bit);
+                    tile_row[1] = (tile_row[1] & !(1 << bit)) | ((shade >> 1) << bit);
+                }
+            }
+        }
+    }
+}
+
+impl Default for PocketCamera {
+    fn default() -> Self {
+        Self {
+            ram_enable: false,
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+            registers: [0; REGISTERS_LEN],
+            capture_cycles: 0,
+            image_source: Box::new(TestPattern),
+        }
+    }
+}
+
+impl Mapper for PocketCamera {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 14usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                // Only the first register can be read, with the capture in progress in bit 0
+                if addr & 0x7F == 0 {
+                    let busy = (self.capture_cycles > 0) as u8;
+                    CartridgeReadTarget::Value((self.registers[0] & 0x06) | busy)
+                } else {
+                    CartridgeReadTarget::Value(0x00)
+                }
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can be read even when disabled
+                CartridgeReadTarget::Ram(self.ram_addr(addr))
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                // Enables or disables writing to the RAM
+                self.ram_enable = data & 0x0F == 0x0A;
+                None
+            }
+            0x2000..=0x3FFF => {
+                // ROM bank number
+                self.rom_bank_number = data & 0x3F;
+                None
+            }
+            0x4000..=0x5FFF => {
+                // RAM bank number, or camera registers
+                self.ram_bank_number = data & 0x1F;
+                None
+            }
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                match (addr & 0x7F) as usize {
+                    0 => {
+                        if data & 0x01 != 0 && self.capture_cycles == 0 {
+                            self.start_capture();
+                        }
+
+                        self.registers[0] = data & 0x06;
+                    }
+                    register if register < REGISTERS_LEN => self.registers[register] = data,
+                    _ => {}
+                }
+                None
+            }
+            0xA000..=0xBFFF => {
+                // RAM range.
+                // Can only be written when enabled
+                if self.ram_enable {
+                    Some(self.ram_addr(addr))
+                } else {
+                    // Ram is disabled, so don't write to it
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn clock(&mut self, cycles: u32, ram: &mut [u8]) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+        if self.capture_cycles == 0 {
+            self.capture(ram);
+        }
+    }
+
+    fn set_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.image_source = image_source;
+    }
+}
+
+impl SaveState for PocketCamera {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bytes(&self.registers);
+        writer.write_u32(self.capture_cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        reader.read_bytes(&mut self.registers)?;
+        self.capture_cycles = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture() {
+        let mut camera = PocketCamera::new();
+        let mut ram = alloc::vec![0u8; 0x20000];
+
+        camera.map_write(0x4000, 0x10);
+
+        // Gain of 1.0, exposure of 1.0 and the same thresholds everywhere
+        camera.map_write(0xA001, 0x04);
+        camera.map_write(0xA002, 0x10);
+        camera.map_write(0xA003, 0x00);
+        for i in 0..16 {
+            camera.map_write(0xA006 + i * 3, 64);
+            camera.map_write(0xA007 + i * 3, 128);
+            camera.map_write(0xA008 + i * 3, 192);
+        }
+
+        camera.map_write(0xA000, 0x01);
+        assert!(matches!(
+            camera.map_read(0xA000),
+            CartridgeReadTarget::Value(1)
+        ));
+
+        camera.clock(129792, &mut ram);
+        assert!(matches!(
+            camera.map_read(0xA000),
+            CartridgeReadTarget::Value(1)
+        ));
+
+        camera.clock(2048 + 0x1000 * 64, &mut ram);
+        assert!(matches!(
+            camera.map_read(0xA000),
+            CartridgeReadTarget::Value(0)
+        ));
+
+        // The gradient goes from black on the left to white on the right
+        assert_eq!(&ram[0x100..0x102], &[0xFF, 0xFF]);
+        assert_eq!(&ram[0x1F0..0x1F2], &[0x00, 0x00]);
+
+        // Each tile column of 8 pixels covers 16 values of the gradient
+        assert_eq!(&ram[0x140..0x142], &[0x00, 0xFF]);
+    }
+}
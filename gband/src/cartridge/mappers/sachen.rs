@@ -0,0 +1,105 @@
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Unlicensed mapper of Sachen games, which also holds their multicarts.
+/// A base bank and a mask select the game, and the ROM bank selects a bank within it.
+///
+/// The cartridge scrambles the header until the boot ROM is done with the logo, which isn't emulated.
+/// These games need to be started without a boot ROM.
+pub struct SachenMmc1 {
+    base_rom_bank: u8,
+    rom_bank_mask: u8,
+    rom_bank_number: u8,
+}
+
+impl SachenMmc1 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// The base and the mask can only be changed while the ROM bank has bits 4 and 5 set
+    fn unlocked(&self) -> bool {
+        self.rom_bank_number & 0x30 == 0x30
+    }
+}
+
+impl Default for SachenMmc1 {
+    fn default() -> Self {
+        Self {
+            base_rom_bank: 0x00,
+            rom_bank_mask: 0x00,
+            rom_bank_number: 0x01,
+        }
+    }
+}
+
+impl Mapper for SachenMmc1 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        let base = self.base_rom_bank & self.rom_bank_mask;
+
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to the first bank of the game
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+
+                CartridgeReadTarget::Rom(((base as usize) << 14usize) | addr)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = base | (self.rom_bank_number & !self.rom_bank_mask);
+
+                CartridgeReadTarget::Rom(((bank as usize) << 14usize) | addr)
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF if self.unlocked() => {
+                // Base ROM bank
+                self.base_rom_bank = data;
+            }
+            0x2000..=0x3FFF => {
+                // ROM bank number
+                if data == 0 {
+                    self.rom_bank_number = 0x01;
+                } else {
+                    self.rom_bank_number = data;
+                }
+            }
+            0x4000..=0x5FFF if self.unlocked() => {
+                // ROM bank mask
+                self.rom_bank_mask = data;
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl SaveState for SachenMmc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.base_rom_bank);
+        writer.write_u8(self.rom_bank_mask);
+        writer.write_u8(self.rom_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.base_rom_bank = reader.read_u8()?;
+        self.rom_bank_mask = reader.read_u8()?;
+        self.rom_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use alloc::vec::Vec;
+
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Bytes of EEPROM-backed RAM
+const RAM_LEN: usize = 32;
+
+/// Size of the footer: the RAM, the clock and the timestamp
+const FOOTER_LEN: usize = RAM_LEN + 7 + 8;
+
+/// Days in 100 years, after which the calendar repeats
+const DAYS_WRAP: u64 = 100 * 365 + 25;
+
+/// Registers selected by writing to 0xA001
+const REG_ROM_BANK_LOW: usize = 0x0;
+const REG_ROM_BANK_HIGH: usize = 0x1;
+const REG_DATA_LOW: usize = 0x4;
+const REG_DATA_HIGH: usize = 0x5;
+const REG_COMMAND: usize = 0x6;
+const REG_ADDRESS: usize = 0x7;
+const REG_RESULT_LOW: usize = 0xC;
+const REG_RESULT_HIGH: usize = 0xD;
+
+/// Calendar clock, read and written one BCD digit at a time
+#[derive(Clone, Copy)]
+struct Rtc {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    weekday: u8,
+    day: u8,
+    month: u8,
+    /// Years since the last leap year, up to 99
+    year: u8,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self {
+            second: 0,
+            minute: 0,
+            hour: 0,
+            weekday: 0,
+            day: 1,
+            month: 1,
+            year: 0,
+        }
+    }
+}
+
+impl Rtc {
+    fn days_in_month(&self) -> u8 {
+        match self.month {
+            2 if self.year.is_multiple_of(4) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let seconds = seconds.saturating_add(self.second as u64);
+        let minutes = seconds / 60 + self.minute as u64;
+        let hours = minutes / 60 + self.hour as u64;
+        let days = hours / 24;
+        self.second = (seconds % 60) as u8;
+        self.minute = (minutes % 60) as u8;
+        self.hour = (hours % 24) as u8;
+        self.weekday = ((self.weekday as u64 + days % 7) % 7) as u8;
+
+        // The calendar repeats every 100 years, which all have a leap year every 4 years
+        for _ in 0..days % DAYS_WRAP {
+            self.day += 1;
+
+            if self.day > self.days_in_month() {
+                self.day = 1;
+                self.month += 1;
+
+                if self.month > 12 {
+                    self.month = 1;
+                    self.year = (self.year + 1) % 100;
+                }
+            }
+        }
+    }
+
+    /// Field of the clock at an address and whether it's the tens digit.
+    /// Seconds are at 0, the weekday at 6 and the tens of years at 12.
+    fn field(&mut self, address: u8) -> Option<(&mut u8, bool)> {
+        let (field, tens) = match address {
+            0 | 1 => (&mut self.second, address == 1),
+            2 | 3 => (&mut self.minute, address == 3),
+            4 | 5 => (&mut self.hour, address == 5),
+            6 => (&mut self.weekday, false),
+            7 | 8 => (&mut self.day, address == 8),
+            9 | 10 => (&mut self.month, address == 10),
+            11 | 12 => (&mut self.year, address == 12),
+            _ => return None,
+        };
+
+        Some((field, tens))
+    }
+
+    fn read_digit(&mut self, address: u8) -> u8 {
+        match self.field(address) {
+            Some((value, false)) => *value % 10,
+            Some((value, true)) => *value / 10,
+            None => 0,
+        }
+    }
+
+    fn write_digit(&mut self, address: u8, digit: u8) {
+        let digit = digit % 10;
+
+        match self.field(address) {
+            Some((value, false)) if address == 6 => *value = digit % 7,
+            Some((value, false)) => *value = *value / 10 * 10 + digit,
+            Some((value, true)) => *value = *value % 10 + digit * 10,
+            None => {}
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 7] {
+        [
+            self.second,
+            self.minute,
+            self.hour,
+            self.weekday,
+            self.day,
+            self.month,
+            self.year,
+        ]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            second: bytes[0] % 60,
+            minute: bytes[1] % 60,
+            hour: bytes[2] % 24,
+            weekday: bytes[3] % 7,
+            day: bytes[4].clamp(1, 31),
+            month: bytes[5].clamp(1, 12),
+            year: bytes[6] % 100,
+        }
+    }
+}
+
+/// Mapper of Tamagotchi 3.
+/// Everything goes through 16 registers of 4 bits, selected at 0xA001 and accessed at 0xA000.
+pub struct Tama5 {
+    registers: [u8; 16],
+    selected: usize,
+
+    ram: [u8; RAM_LEN],
+    rtc: Rtc,
+
+    /// UNIX time matching the clock
+    timestamp: Option<u64>,
+}
+
+impl Tama5 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn data(&self) -> u8 {
+        (self.registers[REG_DATA_HIGH] << 4) | self.registers[REG_DATA_LOW]
+    }
+
+    fn set_result(&mut self, value: u8) {
+        self.registers[REG_RESULT_LOW] = value & 0x0F;
+        self.registers[REG_RESULT_HIGH] = value >> 4;
+    }
+
+    /// Writing the low address executes the command
+    fn execute(&mut self) {
+        let command = self.registers[REG_COMMAND];
+        let address = ((command & 0x01) << 4) | self.registers[REG_ADDRESS];
+
+        match command >> 1 {
+            0x0 => self.ram[address as usize] = self.data(),
+            0x1 => self.set_result(self.ram[address as usize]),
+            0x2 => self.rtc.write_digit(address & 0x0F, self.data()),
+            0x3 => {
+                let digit = self.rtc.read_digit(address & 0x0F);
+                self.set_result(digit);
+            }
+            _ => log::warn!("Unknown TAMA5 command: {command:x}"),
+        }
+    }
+}
+
+impl Default for Tama5 {
+    fn default() -> Self {
+        Self {
+            registers: [0; 16],
+            selected: 0,
+            ram: [0; RAM_LEN],
+            rtc: Default::default(),
+            timestamp: None,
+        }
+    }
+}
+
+impl Mapper for Tama5 {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x3FFF => {
+                // First bank
+                // Fixed to bank 0
+                let mask = 0x3FFF;
+                CartridgeReadTarget::Rom((addr & mask) as usize)
+            }
+            0x4000..=0x7FFF => {
+                // Switchable bank
+                let mask = 0x3FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (((self.registers[REG_ROM_BANK_HIGH] & 0x01) << 4)
+                    | self.registers[REG_ROM_BANK_LOW]) as usize;
+
+                CartridgeReadTarget::Rom((bank << 14usize) | addr)
+            }
+            0xA000..=0xBFFF => match addr & 0x01 {
+                // Only the result registers can be read
+                0 => match self.selected {
+                    REG_RESULT_LOW | REG_RESULT_HIGH => {
+                        CartridgeReadTarget::Value(0xF0 | self.registers[self.selected])
+                    }
+                    _ => CartridgeReadTarget::Value(0xFF),
+                },
+                // Commands are executed instantly, so it is always ready
+                _ => CartridgeReadTarget::Value(0xF1),
+            },
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, data: u8) -> Option<usize> {
+        if let 0xA000..=0xBFFF = addr {
+            if addr & 0x01 == 0 {
+                self.registers[self.selected] = data & 0x0F;
+
+                if self.selected == REG_ADDRESS {
+                    self.execute();
+                }
+            } else {
+                self.selected = (data & 0x0F) as usize;
+            }
+        }
+
+        None
+    }
+
+    fn set_time(&mut self, now: u64) {
+        // Time going backwards, like with a new time source, doesn't move the clock back
+        if let Some(timestamp) = self.timestamp {
+            if now > timestamp {
+                self.rtc.advance(now - timestamp);
+            }
+        }
+
+        self.timestamp = Some(now);
+    }
+
+    fn save_footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        footer.extend_from_slice(&self.ram);
+        footer.extend_from_slice(&self.rtc.to_bytes());
+        footer.extend_from_slice(&self.timestamp.unwrap_or_default().to_le_bytes());
+        footer
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FOOTER_LEN {
+            return false;
+        }
+
+        self.ram.copy_from_slice(&footer[..RAM_LEN]);
+        self.rtc = Rtc::from_bytes(&footer[RAM_LEN..RAM_LEN + 7]);
+        self.timestamp = match u64::from_le_bytes(footer[RAM_LEN + 7..].try_into().unwrap()) {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+        true
+    }
+}
+
+impl SaveState for Tama5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_u8(self.selected as u8);
+        writer.write_bytes(&self.save_footer());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.registers)?;
+        self.selected = (reader.read_u8()? & 0x0F) as usize;
+
+        let mut footer = [0u8; FOOTER_LEN];
+        reader.read_bytes(&mut footer)?;
+        self.load_footer(&footer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_register(tama5: &mut Tama5, register: usize, value: u8) {
+        tama5.map_write(0xA001, register as u8);
+        tama5.map_write(0xA000, value);
+    }
+
+    fn command(tama5: &mut Tama5, command: u8, address: u8, data: u8) -> u8 {
+        write_register(tama5, REG_DATA_LOW, data & 0x0F);
+        write_register(tama5, REG_DATA_HIGH, data >> 4);
+        write_register(tama5, REG_COMMAND, (command << 1) | (address >> 4));
+        write_register(tama5, REG_ADDRESS, address & 0x0F);
+
+        let mut result = 0;
+        for register in [REG_RESULT_HIGH, REG_RESULT_LOW] {
+            tama5.map_write(0xA001, register as u8);
+            if let CartridgeReadTarget::Value(value) = tama5.map_read(0xA000) {
+                result = (result << 4) | (value & 0x0F);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_ram_and_rtc() {
+        let mut tama5 = Tama5::new();
+
+        command(&mut tama5, 0x0, 0x1F, 0xA5);
+        assert_eq!(command(&mut tama5, 0x1, 0x1F, 0x00), 0xA5);
+
+        // Set 23:59:50 on December 31st
+        for (address, digit) in [(0, 0), (1, 5), (2, 9), (3, 5), (4, 3), (5, 2)] {
+            command(&mut tama5, 0x2, address, digit);
+        }
+        for (address, digit) in [(7, 1), (8, 3), (9, 2), (10, 1)] {
+            command(&mut tama5, 0x2, address, digit);
+        }
+
+        tama5.set_time(1000);
+        tama5.set_time(1010);
+
+        let digits: Vec<u8> = (0..13).map(|i| command(&mut tama5, 0x3, i, 0)).collect();
+        assert_eq!(digits, [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 0, 1, 0]);
+
+        let mut loaded = Tama5::new();
+        assert!(loaded.load_footer(&tama5.save_footer()));
+        assert_eq!(loaded.ram[0x1F], 0xA5);
+        assert_eq!(loaded.rtc.year, 1);
+    }
+
+    #[test]
+    fn test_rtc_wrap() {
+        let mut rtc = Rtc::default();
+
+        // 100 years and a day later, which is a whole number of weeks
+        rtc.advance((DAYS_WRAP + 1) * 24 * 60 * 60);
+        assert_eq!(rtc.to_bytes(), [0, 0, 0, 0, 2, 1, 0]);
+
+        // Huge jumps don't overflow
+        rtc.advance(u64::MAX);
+        assert!(rtc.day >= 1 && rtc.day <= 31);
+    }
+}
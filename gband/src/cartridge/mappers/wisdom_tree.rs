@@ -0,0 +1,53 @@
+use super::Mapper;
+use crate::cartridge::CartridgeReadTarget;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Unlicensed mapper of Wisdom Tree games.
+/// The low byte of the address written to selects a 32 KiB bank mapped over the whole ROM range.
+pub struct WisdomTree {
+    rom_bank_number: u8,
+}
+
+impl WisdomTree {
+    pub fn new() -> Self {
+        Self { rom_bank_number: 0 }
+    }
+}
+
+impl Mapper for WisdomTree {
+    fn map_read(&self, addr: u16) -> CartridgeReadTarget {
+        match addr {
+            0x0000..=0x7FFF => {
+                let mask = 0x7FFF;
+                let addr = (addr & mask) as usize;
+                let bank = (self.rom_bank_number as usize) << 15usize;
+
+                CartridgeReadTarget::Rom(bank | addr)
+            }
+            _ => {
+                log::warn!("Read on cartridge at {addr}, which isn't supposed to be mapped to the cartridge");
+                CartridgeReadTarget::Error
+            }
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, _data: u8) -> Option<usize> {
+        if let 0x0000..=0x3FFF = addr {
+            // The data is ignored
+            self.rom_bank_number = addr as u8;
+        }
+
+        None
+    }
+}
+
+impl SaveState for WisdomTree {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+}
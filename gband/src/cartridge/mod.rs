@@ -1,19 +1,34 @@
+mod detection;
 mod header;
 mod mappers;
+mod registry;
 
 use alloc::boxed::Box;
-use header::{CartridgeType, Header, RamBanks};
+use alloc::vec::Vec;
+use detection::detect_unlicensed_mapper;
+use header::NINTENDO_LOGO;
 use mappers::*;
 
-pub use header::RomParserError;
+use crate::image_source::ImageSource;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
-use self::header::CgbFlag;
+pub use detection::MapperKind;
+pub use header::{CartridgeType, CgbFlag, Header, RamBanks, RomParserError};
+pub use mappers::Mapper;
+pub use registry::{MapperFactory, MapperRegistry};
 
+/// What a read from the cartridge is mapped to
 pub enum CartridgeReadTarget {
+    /// Open bus, reads 0
     Error,
+    /// Offset in the ROM
     Rom(usize),
+    /// Offset in the RAM
     Ram(usize),
+    /// Offset in the RAM, of which only the low nibble is connected
     RamHalf(usize),
+    /// Value from the mapper itself, like a register
+    Value(u8),
 }
 
 pub struct Cartridge {
@@ -25,16 +40,54 @@ pub struct Cartridge {
 
 impl Cartridge {
     pub fn load(rom: &[u8], save_data: Option<&[u8]>) -> Result<Self, RomParserError> {
+        Self::load_with_mapper(rom, save_data, None, &MapperRegistry::new())
+    }
+
+    /// Loads a cartridge with a specific mapper instead of the one from the header,
+    /// or with one of the custom mappers
+    pub fn load_with_mapper(
+        rom: &[u8],
+        save_data: Option<&[u8]>,
+        mapper: Option<MapperKind>,
+        custom_mappers: &MapperRegistry,
+    ) -> Result<Self, RomParserError> {
         if rom.len() < 0x150 {
             return Err(RomParserError::TooShort);
         };
 
-        let header = Header::try_from(&rom[0x100..0x150])?;
+        // MMM01 compilations boot on their menu, which is at the end of the ROM with the real header
+        let mmm01_header = Header::try_from(&rom[mmm01_header_offset(rom)..])
+            .ok()
+            .filter(|header| header.cartridge_type.is_mmm01());
+
+        // Unlicensed cartridges can't be trusted with their header
+        let forced_mapper = match mmm01_header {
+            Some(_) => mapper,
+            None => mapper.or_else(|| detect_unlicensed_mapper(rom)),
+        };
+
+        let (header, header_error) = match mmm01_header {
+            Some(header) => (header, None),
+            None => match Header::try_from(&rom[0x100..0x150]) {
+                Ok(header) => (header, None),
+                Err(e) => (Header::parse_unverified(&rom[0x100..0x150])?, Some(e)),
+            },
+        };
         log::info!("{header:x?}");
 
+        let custom_mapper = custom_mappers.create(&header, rom);
+
+        if let Some(e) = header_error {
+            if custom_mapper.is_none() && forced_mapper.is_none() {
+                return Err(e);
+            }
+
+            log::warn!("Invalid header ({e}), ignored for the chosen mapper");
+        }
+
         let rom = rom.to_vec();
 
-        let mut ram = match header.ram_banks {
+        let ram = match header.ram_banks {
             RamBanks::Banks(n) => {
                 // 1 bank is 8 KiB
                 Some(alloc::vec![0u8; n * 8 * 1024])
@@ -43,55 +96,97 @@ impl Cartridge {
             _ => None,
         };
 
-        // Load in save data
-        match (&mut ram, save_data) {
-            (Some(r), Some(s)) => {
-                if r.len() == s.len() {
-                    r.copy_from_slice(s);
-                } else {
-                    log::warn!(
-                        "Couldn't load save as the size doesn't match. Ram: {:x}, Save: {:x}",
-                        r.len(),
-                        s.len()
-                    )
-                }
-            }
-            _ => {}
+        let mapper_kind = forced_mapper.unwrap_or_else(|| header.cartridge_type.into());
+        let mapper: Box<dyn Mapper> = match custom_mapper {
+            Some(mapper) => mapper,
+            None => Self::builtin_mapper(mapper_kind, &header, &rom),
+        };
+
+        let mut cartridge = Self {
+            header,
+            rom,
+            ram,
+            mapper,
         };
 
+        if let Some(save_data) = save_data {
+            cartridge.load_save_data(save_data);
+        }
+
+        Ok(cartridge)
+    }
+
+    fn builtin_mapper(mapper_kind: MapperKind, header: &Header, rom: &[u8]) -> Box<dyn Mapper> {
         let ram_banks = match header.ram_banks {
             RamBanks::Banks(x) => x,
             _ => 0,
         };
 
-        let mapper: Box<dyn Mapper> = match header.cartridge_type {
-            CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery => {
-                Box::new(NoMapper)
+        // Bootleg boards can be larger than what the header says
+        let rom_banks = header.rom_banks.max(rom.len() / 0x4000);
+
+        match mapper_kind {
+            MapperKind::NoMapper => Box::new(NoMapper),
+            MapperKind::Mbc1 => Box::new(Mbc1::new(rom_banks, ram_banks, is_mbc1_multicart(rom))),
+            MapperKind::Mbc2 => Box::new(Mbc2::new()),
+            MapperKind::Mmm01 => Box::new(Mmm01::new()),
+            MapperKind::Mbc3 => {
+                let has_rtc = matches!(
+                    header.cartridge_type,
+                    CartridgeType::Mbc3TimerBattery | CartridgeType::Mbc3TimerRamBattery
+                );
+
+                // The MBC30 is only used by carts too large for the MBC3
+                let mbc30 = rom_banks > 128 || ram_banks > 4;
+                Box::new(Mbc3::new(has_rtc, mbc30))
             }
-            CartridgeType::Mbc1 | CartridgeType::Mbc1Ram | CartridgeType::Mbc1RamBattery => {
-                Box::new(Mbc1::new(header.rom_banks, ram_banks))
+            MapperKind::Mbc5 => {
+                let has_rumble = matches!(
+                    header.cartridge_type,
+                    CartridgeType::Mbc5Rumble
+                        | CartridgeType::Mbc5RumbleRam
+                        | CartridgeType::Mbc5RumbleRamBattery
+                );
+                Box::new(Mbc5::new(has_rumble))
             }
-            CartridgeType::Mbc2 | CartridgeType::Mbc2Battery => Box::new(Mbc2::new()),
-            CartridgeType::Mbc3TimerBattery
-            | CartridgeType::Mbc3TimerRamBattery
-            | CartridgeType::Mbc3
-            | CartridgeType::Mbc3Ram
-            | CartridgeType::Mbc3RamBattery => Box::new(Mbc3::new()),
-            CartridgeType::Mbc5
-            | CartridgeType::Mbc5Ram
-            | CartridgeType::Mbc5RamBattery
-            | CartridgeType::Mbc5Rumble
-            | CartridgeType::Mbc5RumbleRam
-            | CartridgeType::Mbc5RumbleRamBattery => Box::new(Mbc5::new()),
-            _ => return Err(RomParserError::MapperNotImplemented),
-        };
+            MapperKind::Mbc6 => Box::new(Mbc6::new()),
+            MapperKind::Mbc7 => Box::new(Mbc7::new()),
+            MapperKind::HuC1 => Box::new(HuC1::new()),
+            MapperKind::HuC3 => Box::new(HuC3::new()),
+            MapperKind::PocketCamera => Box::new(PocketCamera::new()),
+            MapperKind::Tama5 => Box::new(Tama5::new()),
+            MapperKind::WisdomTree => Box::new(WisdomTree::new()),
+            MapperKind::SachenMmc1 => Box::new(SachenMmc1::new()),
+            MapperKind::M161 => Box::new(M161::new()),
+        }
+    }
 
-        Ok(Self {
-            header,
-            rom,
-            ram,
-            mapper,
-        })
+    fn load_save_data(&mut self, save_data: &[u8]) {
+        let ram_len = self.ram.as_ref().map(|r| r.len()).unwrap_or_default();
+        if save_data.len() < ram_len {
+            log::warn!(
+                "Couldn't load save as the size doesn't match. Ram: {:x}, Save: {:x}",
+                ram_len,
+                save_data.len()
+            );
+            return;
+        }
+
+        let (ram_data, footer) = save_data.split_at(ram_len);
+
+        // Extra data after the RAM, like a clock, is handled by the mapper
+        if !footer.is_empty() && !self.mapper.load_footer(footer) {
+            log::warn!(
+                "Couldn't load save as the size doesn't match. Ram: {:x}, Save: {:x}",
+                ram_len,
+                save_data.len()
+            );
+            return;
+        }
+
+        if let Some(ram) = &mut self.ram {
+            ram.copy_from_slice(ram_data);
+        }
     }
 
     pub fn read(&self, addr: u16) -> u8 {
@@ -116,6 +211,7 @@ impl Cartridge {
                     0
                 }
             },
+            CartridgeReadTarget::Value(value) => value,
         }
     }
 
@@ -135,13 +231,86 @@ impl Cartridge {
         };
     }
 
-    pub fn get_save_data(&self) -> Option<&[u8]> {
+    /// RAM content followed by the footer of the mapper, if any
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        let footer = self.mapper.save_footer();
+
         match &self.ram {
-            Some(r) => Some(r),
+            Some(r) => {
+                let mut data = r.clone();
+                data.extend_from_slice(&footer);
+                Some(data)
+            }
+            None if !footer.is_empty() => Some(footer),
             None => None,
         }
     }
 
+    pub fn clock(&mut self, cycles: u32) {
+        let ram = self.ram.as_deref_mut().unwrap_or_default();
+        self.mapper.clock(cycles, ram)
+    }
+
+    pub fn set_time(&mut self, now: u64) {
+        self.mapper.set_time(now)
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.mapper.set_tilt(x, y)
+    }
+
+    pub fn ir_led(&self) -> Option<bool> {
+        self.mapper.ir_led()
+    }
+
+    pub fn set_ir_light(&mut self, light: bool) {
+        self.mapper.set_ir_light(light)
+    }
+
+    pub fn set_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.mapper.set_image_source(image_source)
+    }
+
+    /// Internal registers of the mapper, for debuggers
+    pub fn mapper_registers(&self) -> Vec<(&'static str, u32)> {
+        self.mapper.registers()
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.mapper.rumble()
+    }
+
+    /// Sound made by the cartridge, which goes to the Vin pin of the APU
+    pub fn audio_output(&self, cycle_count: u64) -> Option<f32> {
+        self.mapper.audio_output(cycle_count)
+    }
+
+    /// Identifies the ROM in save states, so a state can't be loaded in another game
+    pub fn rom_id(&self) -> Vec<u8> {
+        let mut id = self.header.title.to_vec();
+        id.push(self.header.header_checksum);
+        id.extend_from_slice(&self.header.global_checksum);
+        id
+    }
+
+    /// Sum of the title bytes, used by the CGB boot ROM to detect Nintendo games.
+    /// Returns None for other licensees.
+    pub fn title_checksum(&self) -> Option<u8> {
+        let is_nintendo = self.header.old_licensee_code == 0x01
+            || (self.header.old_licensee_code == 0x33 && &self.header.licensee_code == b"01");
+
+        if is_nintendo {
+            Some(
+                self.header
+                    .title
+                    .iter()
+                    .fold(0u8, |sum, x| sum.wrapping_add(*x)),
+            )
+        } else {
+            None
+        }
+    }
+
     pub fn is_cgb(&self) -> bool {
         !matches!(self.header.cgb_flag, CgbFlag::NoCgb)
     }
@@ -170,3 +339,254 @@ impl Cartridge {
         }
     }
 }
+
+/// Offset of the header in the last 32 KiB of the ROM, where MMM01 compilations keep their menu
+fn mmm01_header_offset(rom: &[u8]) -> usize {
+    rom.len().saturating_sub(0x8000) + 0x100
+}
+
+/// MBC1 multicarts hold several games, each with its own header at the start of a 256 KiB block
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    // All known multicarts are 8 Mbit, the size of four games
+    if rom.len() != 0x100000 {
+        return false;
+    }
+
+    let games = rom
+        .chunks_exact(0x40000)
+        .filter(|game| game[0x104..0x134] == NINTENDO_LOGO)
+        .count();
+
+    games > 1
+}
+
+impl SaveState for Cartridge {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_slice(self.ram.as_deref().unwrap_or_default());
+        self.mapper.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        match &mut self.ram {
+            Some(ram) => reader.read_slice(ram)?,
+            None => reader.read_slice(&mut [])?,
+        };
+
+        self.mapper.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mbc1_multicart() {
+        // Four games of 16 banks, with every bank starting with its number
+        let mut rom = alloc::vec![0u8; 0x100000];
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+
+        for game in rom.chunks_exact_mut(0x40000).take(2) {
+            game[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+            game[0x147] = 0x01; // MBC1
+            game[0x148] = 0x05; // 1 MiB
+            game[0x14D] = 0x00u8.wrapping_sub(0x19).wrapping_sub(0x01 + 0x05);
+        }
+
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+
+        // Only 4 bits of the low bank number are used
+        cartridge.write(0x2000, 0x12);
+        assert_eq!(cartridge.read(0x4000), 0x02);
+
+        // Bank 0x10 isn't 0, so it isn't replaced by bank 1
+        cartridge.write(0x2000, 0x10);
+        assert_eq!(cartridge.read(0x4000), 0x00);
+
+        // The upper bits select the game
+        cartridge.write(0x4000, 0x01);
+        cartridge.write(0x2000, 0x03);
+        assert_eq!(cartridge.read(0x4000), 0x13);
+        assert_eq!(cartridge.read(0x0000), 0x00);
+
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0x0000), 0x10);
+
+        // A single game of the same size uses the regular wiring
+        rom[0x40104] = 0;
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+        cartridge.write(0x2000, 0x12);
+        assert_eq!(cartridge.read(0x4000), 0x12);
+    }
+
+    #[test]
+    fn test_mbc30() {
+        // 4 MiB of ROM, with every bank starting with its number
+        let mut rom = alloc::vec![0u8; 0x400000];
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+
+        rom[0x147] = 0x13; // MBC3 + RAM + Battery
+        rom[0x148] = 0x07; // 4 MiB
+        rom[0x149] = 0x05; // 64 KiB
+        rom[0x14D] = 0x00u8.wrapping_sub(0x19).wrapping_sub(0x13 + 0x07 + 0x05);
+
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+
+        cartridge.write(0x2000, 0xFF);
+        assert_eq!(cartridge.read(0x4000), 0xFF);
+
+        cartridge.write(0x0000, 0x0A);
+        for bank in 0..8 {
+            cartridge.write(0x4000, bank);
+            cartridge.write(0xA000, bank + 1);
+        }
+
+        let save = cartridge.get_save_data().expect("The cartridge has RAM");
+        assert_eq!(save.len(), 0x10000);
+
+        let mut cartridge = Cartridge::load(&rom, Some(&save)).expect("Invalid Rom!");
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x07);
+        assert_eq!(cartridge.read(0xA000), 0x08);
+    }
+
+    #[test]
+    fn test_mmm01() {
+        // 512 KiB of ROM, with every bank starting with its number
+        let mut rom = alloc::vec![0u8; 0x80000];
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+
+        // The header of the menu is in the last 32 KiB
+        let menu = &mut rom[0x78000..];
+        menu[0x147] = 0x0B; // MMM01
+        menu[0x148] = 0x04; // 512 KiB
+        menu[0x14D] = 0x00u8.wrapping_sub(0x19).wrapping_sub(0x0B + 0x04);
+
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+
+        // Boots on the menu
+        assert_eq!(cartridge.read(0x0000), 30);
+        assert_eq!(cartridge.read(0x4000), 31);
+
+        // Map a game of 8 banks starting at bank 8, locking the bits 3-4 of the bank
+        cartridge.write(0x2000, 0x08);
+        cartridge.write(0x6000, 0b1100 << 2);
+        cartridge.write(0x0000, 0x40);
+
+        assert_eq!(cartridge.read(0x0000), 8);
+        assert_eq!(cartridge.read(0x4000), 9);
+
+        cartridge.write(0x2000, 0x1F);
+        assert_eq!(cartridge.read(0x4000), 15);
+
+        // The game can't change its mask or go back to the menu
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x0000, 0x00);
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x0000), 8);
+        assert_eq!(cartridge.read(0x4000), 9);
+    }
+
+    #[test]
+    fn test_mbc5_rumble() {
+        let mut rom = alloc::vec![0u8; 0x8000];
+        rom[0x147] = 0x1E; // MBC5 + Rumble + RAM + Battery
+        rom[0x149] = 0x03; // 32 KiB
+        rom[0x14D] = 0x00u8.wrapping_sub(0x19).wrapping_sub(0x1E + 0x03);
+
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+
+        // The motor bit doesn't select another bank
+        cartridge.write(0x4000, 0x08);
+        assert!(cartridge.rumble());
+        assert_eq!(cartridge.read(0xA000), 0x12);
+
+        cartridge.write(0x4000, 0x00);
+        assert!(!cartridge.rumble());
+    }
+
+    #[test]
+    fn test_unlicensed_mappers() {
+        // 256 KiB of ROM, with every bank starting with its number
+        let mut rom = alloc::vec![0u8; 0x40000];
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+
+        // A ROM only header with more than 32 KiB is wired like an MBC1
+        rom[0x14D] = 0x00u8.wrapping_sub(0x19);
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+        cartridge.write(0x2000, 0x0F);
+        assert_eq!(cartridge.read(0x4000), 0x0F);
+
+        // Wisdom Tree games have an unknown cartridge type and a wrong checksum
+        rom[0x134..0x13F].copy_from_slice(b"WISDOM TREE");
+        rom[0x147] = 0xC0;
+        let mut cartridge = Cartridge::load(&rom, None).expect("Invalid Rom!");
+        cartridge.write(0x0003, 0x00);
+        assert_eq!(cartridge.read(0x0000), 6);
+        assert_eq!(cartridge.read(0x4000), 7);
+
+        // The mapper can be forced
+        let mut cartridge =
+            Cartridge::load_with_mapper(&rom, None, Some(MapperKind::M161), &MapperRegistry::new())
+                .expect("Invalid Rom!");
+        cartridge.write(0x4000, 0x02);
+        cartridge.write(0x4000, 0x03);
+        assert_eq!(cartridge.read(0x0000), 4);
+    }
+
+    #[test]
+    fn test_custom_mapper() {
+        struct ValueMapper(u8);
+
+        impl Mapper for ValueMapper {
+            fn map_read(&self, _addr: u16) -> CartridgeReadTarget {
+                CartridgeReadTarget::Value(self.0)
+            }
+
+            fn map_write(&mut self, _addr: u16, data: u8) -> Option<usize> {
+                self.0 = data;
+                None
+            }
+        }
+
+        impl SaveState for ValueMapper {
+            fn save_state(&self, writer: &mut StateWriter) {
+                writer.write_u8(self.0);
+            }
+
+            fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+                self.0 = reader.read_u8()?;
+                Ok(())
+            }
+        }
+
+        // An unknown cartridge type, with a wrong checksum
+        let mut rom = alloc::vec![0u8; 0x8000];
+        rom[0x147] = 0xAB;
+
+        let mut registry = MapperRegistry::new();
+        registry.register(|header: &Header, _rom: &[u8]| -> Option<Box<dyn Mapper>> {
+            (header.title[0] == b'V').then(|| Box::new(ValueMapper(0x42)) as Box<dyn Mapper>)
+        });
+
+        assert!(Cartridge::load_with_mapper(&rom, None, None, &registry).is_err());
+
+        rom[0x134] = b'V';
+        let mut cartridge =
+            Cartridge::load_with_mapper(&rom, None, None, &registry).expect("Invalid Rom!");
+        assert_eq!(cartridge.read(0x0000), 0x42);
+
+        cartridge.write(0x2000, 0x24);
+        assert_eq!(cartridge.read(0x4000), 0x24);
+    }
+}
@@ -0,0 +1,49 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::header::Header;
+use super::mappers::Mapper;
+
+/// Provides mappers that aren't part of gband, like homebrew hardware or flash carts
+pub trait MapperFactory: Send + Sync {
+    /// Creates the mapper of a cartridge.
+    /// Returns None to leave the cartridge to the next factory, or to the built-in mappers.
+    fn create(&self, header: &Header, rom: &[u8]) -> Option<Box<dyn Mapper>>;
+}
+
+impl<F> MapperFactory for F
+where
+    F: Fn(&Header, &[u8]) -> Option<Box<dyn Mapper>> + Send + Sync,
+{
+    fn create(&self, header: &Header, rom: &[u8]) -> Option<Box<dyn Mapper>> {
+        self(header, rom)
+    }
+}
+
+/// Custom mappers, tried in registration order before the built-in ones
+#[derive(Default)]
+pub struct MapperRegistry {
+    factories: Vec<Box<dyn MapperFactory>>,
+}
+
+impl MapperRegistry {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    pub fn register(&mut self, factory: impl MapperFactory + 'static) {
+        self.factories.push(Box::new(factory));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factories.is_empty()
+    }
+
+    pub(super) fn create(&self, header: &Header, rom: &[u8]) -> Option<Box<dyn Mapper>> {
+        self.factories
+            .iter()
+            .find_map(|factory| factory.create(header, rom))
+    }
+}
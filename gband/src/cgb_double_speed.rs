@@ -1,5 +1,7 @@
 use bitflags::bitflags;
 
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 bitflags! {
     pub struct CgbDoubleSpeed: u8 {
         const PENDING = 0x01;
@@ -13,3 +15,14 @@ impl Default for CgbDoubleSpeed {
         CgbDoubleSpeed::UNUSED
     }
 }
+
+impl SaveState for CgbDoubleSpeed {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = CgbDoubleSpeed::from_bits_truncate(reader.read_u8()?);
+        Ok(())
+    }
+}
@@ -5,7 +5,8 @@ pub mod debugger;
 
 use bitflags::bitflags;
 
-use crate::{bus::CpuBus, CgbDoubleSpeed, InterruptReg, OamDma};
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::{bus::CpuBus, CgbDoubleSpeed, HardwareModel, InterruptReg, OamDma};
 use decoder::{
     Alu, Condition, OpMemAddress16, OpMemAddress8, Opcode, OpcodeCB, Register, RegisterPair, Rot,
 };
@@ -32,8 +33,12 @@ pub struct Cpu {
     pub sp: u16,
     pub pc: u16,
 
+    /// Address of the last fetched opcode, which is the instruction being executed
+    pub instruction_address: u16,
+
     pub cycles: u8,
     pub opcode_latch: Opcode,
+    opcode_byte: u8,
     pub interrupt_master_enable: bool,
     pub ime_pending: Option<bool>,
     pub halted: bool,
@@ -55,9 +60,11 @@ impl Default for Cpu {
 
             sp: 0xFFFE,
             pc: 0x0100,
+            instruction_address: 0x0100,
 
             cycles: 0,
             opcode_latch: Opcode::Unknown,
+            opcode_byte: 0,
             interrupt_master_enable: false,
             ime_pending: None,
             halted: false,
@@ -67,10 +74,100 @@ impl Default for Cpu {
 }
 
 impl Cpu {
-    pub fn clock(&mut self, bus: &mut CpuBus) {
+    /// Registers at power on, before the boot ROM runs
+    pub fn power_on() -> Self {
+        Self {
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            a: 0,
+            f: FlagRegister::empty(),
+
+            sp: 0,
+            pc: 0,
+            instruction_address: 0,
+
+            ..Default::default()
+        }
+    }
+
+    /// Registers left by the boot ROM of each model.
+    /// `title_checksum` is only set for Nintendo games, which the CGB boot ROM colorizes.
+    pub fn after_boot(
+        model: HardwareModel,
+        cgb_mode: bool,
+        header_checksum: u8,
+        title_checksum: Option<u8>,
+    ) -> Self {
+        let mut cpu = Self::default();
+
+        match model {
+            HardwareModel::Dmg | HardwareModel::Mgb => {
+                cpu.a = if model == HardwareModel::Dmg {
+                    0x01
+                } else {
+                    0xFF
+                };
+                cpu.f = if header_checksum == 0 {
+                    FlagRegister::Z
+                } else {
+                    FlagRegister::Z | FlagRegister::H | FlagRegister::C
+                };
+                cpu.b = 0x00;
+                cpu.c = 0x13;
+                cpu.d = 0x00;
+                cpu.e = 0xD8;
+                cpu.h = 0x01;
+                cpu.l = 0x4D;
+            }
+            HardwareModel::Sgb => {
+                cpu.a = 0x01;
+                cpu.f = FlagRegister::empty();
+                cpu.b = 0x00;
+                cpu.c = 0x14;
+                cpu.d = 0x00;
+                cpu.e = 0x00;
+                cpu.h = 0xC0;
+                cpu.l = 0x60;
+            }
+            HardwareModel::Cgb | HardwareModel::Agb => {
+                // Default values are the ones for CGB mode
+                if !cgb_mode {
+                    cpu.b = title_checksum.unwrap_or(0);
+                    cpu.d = 0x00;
+                    cpu.e = 0x08;
+
+                    let hl: u16 = if title_checksum.is_some() {
+                        0x991A
+                    } else {
+                        0x007C
+                    };
+                    cpu.h = (hl >> 8) as u8;
+                    cpu.l = hl as u8;
+                }
+
+                if model == HardwareModel::Agb {
+                    // The AGB boot ROM ends with an extra `inc b`
+                    let b = cpu.b.wrapping_add(1);
+                    cpu.f = FlagRegister::empty();
+                    cpu.f.set(FlagRegister::Z, b == 0);
+                    cpu.f.set(FlagRegister::H, b & 0x0F == 0);
+                    cpu.b = b;
+                }
+            }
+        }
+
+        cpu
+    }
+
+    /// Run one M-cycle. Returns true if a new instruction was fetched during that cycle.
+    pub fn clock(&mut self, bus: &mut CpuBus) -> bool {
         if self.handle_dma(bus) {
             // CPU is hanged while executing HDMA
-            return;
+            return false;
         };
 
         // TODO: Don't clock if the CPU is STOPped
@@ -106,8 +203,34 @@ impl Cpu {
 
             if !self.halted {
                 self.fetch(bus);
+                return true;
             }
         }
+
+        false
+    }
+
+    /// Number of CPU cycles where the CPU doesn't touch the rest of the hardware.
+    /// Instructions run on their first cycle, and the next instruction is fetched on the last one.
+    pub fn idle_cycles(&self, interrupt_pending: bool) -> u32 {
+        if self.halted {
+            if self.cycles == 0 && self.ime_pending.is_none() && !interrupt_pending {
+                u32::MAX
+            } else {
+                0
+            }
+        } else if matches!(self.opcode_latch, Opcode::Unknown) && self.cycles >= 2 {
+            self.cycles as u32 - 1
+        } else {
+            0
+        }
+    }
+
+    /// Skip cycles returned by `idle_cycles`
+    pub fn skip(&mut self, cycles: u32) {
+        if !self.halted {
+            self.cycles -= cycles as u8;
+        }
     }
 
     fn handle_interrupt(&mut self, bus: &mut CpuBus) {
@@ -139,7 +262,9 @@ impl Cpu {
 
     // TODO: Remove pub added for criterion
     pub fn fetch(&mut self, bus: &mut CpuBus) {
-        self.opcode_latch = Opcode::from(self.read_immediate(bus));
+        self.instruction_address = self.pc;
+        self.opcode_byte = self.read_immediate(bus);
+        self.opcode_latch = Opcode::from(self.opcode_byte);
         self.cycles = self.opcode_latch.cycles();
 
         if self.halt_bug_active {
@@ -878,18 +1003,94 @@ impl Cpu {
     }
 }
 
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for reg in [self.b, self.c, self.d, self.e, self.h, self.l, self.a] {
+            writer.write_u8(reg);
+        }
+        writer.write_u8(self.f.bits());
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+        writer.write_u16(self.instruction_address);
+
+        writer.write_u8(self.cycles);
+
+        // The latch is cleared after the first execute cycle, so we need to remember if it's still valid
+        writer.write_bool(!matches!(self.opcode_latch, Opcode::Unknown));
+        writer.write_u8(self.opcode_byte);
+
+        writer.write_bool(self.interrupt_master_enable);
+        writer.write_u8(match self.ime_pending {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        });
+        writer.write_bool(self.halted);
+        writer.write_bool(self.halt_bug_active);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for reg in [
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+            &mut self.a,
+        ] {
+            *reg = reader.read_u8()?;
+        }
+        self.f = FlagRegister::from_bits_truncate(reader.read_u8()?);
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        self.instruction_address = if reader.version() >= 5 {
+            reader.read_u16()?
+        } else {
+            self.pc.wrapping_sub(1)
+        };
+
+        self.cycles = reader.read_u8()?;
+
+        let latch_valid = reader.read_bool()?;
+        self.opcode_byte = reader.read_u8()?;
+        self.opcode_latch = if latch_valid {
+            Opcode::from(self.opcode_byte)
+        } else {
+            Opcode::Unknown
+        };
+
+        self.interrupt_master_enable = reader.read_bool()?;
+        self.ime_pending = match reader.read_u8()? {
+            0 => None,
+            1 => Some(false),
+            2 => Some(true),
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        self.halted = reader.read_bool()?;
+        self.halt_bug_active = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Apu;
+    use crate::BootRom;
     use crate::Cartridge;
     use crate::CgbDoubleSpeed;
     use crate::HDma;
+    use crate::HardwareModel;
     use crate::InterruptState;
+    use crate::IrPort;
     use crate::JoypadState;
     use crate::OamDma;
     use crate::Ppu;
     use crate::RomParserError;
     use crate::SerialPort;
+    use crate::Sgb;
     use crate::TimerRegisters;
     use crate::WRAM_BANK_SIZE;
     use alloc::vec;
@@ -906,10 +1107,15 @@ mod tests {
         pub hdma: HDma,
         pub timer_registers: TimerRegisters,
         pub serial_port: SerialPort,
+        pub ir_port: IrPort,
         pub joypad_state: JoypadState,
         pub joypad_register: u8,
+        pub sgb: Option<Sgb>,
         pub ppu: Ppu,
         pub cgb_mode: bool,
+        pub apu: Apu,
+        pub boot_rom: BootRom,
+        pub model: HardwareModel,
     }
 
     impl MockEmulator {
@@ -930,10 +1136,15 @@ mod tests {
                 hdma: Default::default(),
                 timer_registers: Default::default(),
                 serial_port: Default::default(),
+                ir_port: Default::default(),
                 joypad_state: Default::default(),
                 joypad_register: 0,
+                sgb: None,
                 ppu: Default::default(),
                 cgb_mode: false,
+                apu: Default::default(),
+                boot_rom: Default::default(),
+                model: HardwareModel::Cgb,
             };
 
             Ok(emulator)
@@ -1166,4 +1377,27 @@ mod tests {
         execute_n(&mut emu, 1);
         assert_eq!(emu.cpu.pc, 0xD060 + 1);
     }
+
+    #[test]
+    fn test_after_boot_registers() {
+        let dmg = Cpu::after_boot(HardwareModel::Dmg, false, 0x12, None);
+        assert_eq!(dmg.a, 0x01);
+        assert_eq!(dmg.f, FlagRegister::Z | FlagRegister::H | FlagRegister::C);
+
+        let mgb = Cpu::after_boot(HardwareModel::Mgb, false, 0x00, None);
+        assert_eq!(mgb.a, 0xFF);
+        assert_eq!(mgb.f, FlagRegister::Z);
+
+        let cgb = Cpu::after_boot(HardwareModel::Cgb, true, 0x12, None);
+        assert_eq!(cgb.a, 0x11);
+        assert_eq!(cgb.b, 0x00);
+
+        let agb = Cpu::after_boot(HardwareModel::Agb, true, 0x12, None);
+        assert_eq!(agb.a, 0x11);
+        assert_eq!(agb.b, 0x01);
+
+        let cgb_dmg_mode = Cpu::after_boot(HardwareModel::Cgb, false, 0x12, Some(0x45));
+        assert_eq!(cgb_dmg_mode.b, 0x45);
+        assert_eq!((cgb_dmg_mode.h, cgb_dmg_mode.l), (0x99, 0x1A));
+    }
 }
@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Default, Clone)]
 pub struct OamDma {
     pub cycle: Option<u8>,
@@ -62,3 +64,40 @@ impl HDma {
         self.hblank_latch = false;
     }
 }
+
+impl SaveState for OamDma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.cycle.is_some());
+        writer.write_u8(self.cycle.unwrap_or_default());
+        writer.write_u8(self.source);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let active = reader.read_bool()?;
+        let cycle = reader.read_u8()?;
+        self.cycle = if active { Some(cycle) } else { None };
+        self.source = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for HDma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.source);
+        writer.write_u16(self.destination);
+        writer.write_u8(self.control);
+        writer.write_bool(self.hblank_mode);
+        writer.write_u8(self.cycle);
+        writer.write_bool(self.hblank_latch);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.source = reader.read_u16()?;
+        self.destination = reader.read_u16()?;
+        self.control = reader.read_u8()?;
+        self.hblank_mode = reader.read_bool()?;
+        self.cycle = reader.read_u8()?;
+        self.hblank_latch = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+/// Number of Game Boys the adapter connects
+pub const DMG07_PLAYERS: usize = 4;
+
+/// First byte of the packets of the ping phase
+const PING_HEADER: u8 = 0xFE;
+/// Sent back by a Game Boy for the header and the first status byte of a ping
+const ACK: u8 = 0x88;
+/// Sent by the first player for a whole ping to start the transmission phase
+const START_TRANSMISSION: u8 = 0xAA;
+/// Sent by the adapter to every player before the transmission phase
+const TRANSMISSION_HEADER: u8 = 0xCC;
+/// Sent by the first player for all of its data to go back to the ping phase
+const RESTART: u8 = 0xFF;
+
+/// Length of a ping, the header followed by 3 status bytes
+const PING_LEN: usize = 4;
+
+/// Largest amount of data a player can send in each packet
+const MAX_PACKET_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Find out which Game Boys are connected, and the size of their data
+    Ping,
+    /// Announce the transmission phase
+    Sync,
+    /// Every packet holds the data sent by each player during the previous one
+    Transmission,
+}
+
+/// DMG-07 four player adapter.
+/// It clocks the serial port of every Game Boy at the same time, all of them using the external clock.
+pub struct Dmg07 {
+    phase: Phase,
+    /// Position in the current packet
+    position: usize,
+
+    /// Players that sent both acknowledgements during the last ping
+    connected: u8,
+    acknowledged: u8,
+    /// Whether the first player asked for the transmission phase for the whole ping
+    start_requested: bool,
+    /// Bytes sent by the first player during the current ping
+    first_player_ping: [u8; PING_LEN],
+
+    /// Transfer speed and bytes sent by each player per packet, both picked by the first player
+    rate: u8,
+    size: usize,
+
+    /// Data sent by the players during the previous packet, and during the current one
+    data: [u8; DMG07_PLAYERS * MAX_PACKET_SIZE],
+    next_data: [u8; DMG07_PLAYERS * MAX_PACKET_SIZE],
+}
+
+impl Default for Dmg07 {
+    fn default() -> Self {
+        Self {
+            phase: Phase::Ping,
+            position: 0,
+
+            connected: 0,
+            acknowledged: 0,
+            start_requested: true,
+            first_player_ping: [0; PING_LEN],
+
+            rate: 0,
+            size: 1,
+
+            data: [0; DMG07_PLAYERS * MAX_PACKET_SIZE],
+            next_data: [0; DMG07_PLAYERS * MAX_PACKET_SIZE],
+        }
+    }
+}
+
+impl Dmg07 {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// Transfer speed requested by the first player during the ping phase
+    pub fn rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Bytes sent to each player for the next transfer
+    pub fn outgoing(&self) -> [u8; DMG07_PLAYERS] {
+        let mut bytes = [0; DMG07_PLAYERS];
+
+        for (player, byte) in bytes.iter_mut().enumerate() {
+            *byte = match self.phase {
+                Phase::Ping if self.position == 0 => PING_HEADER,
+                // The connected players in the upper bits, and the player receiving it in the lower bits
+                Phase::Ping => (self.connected << 4) | (player as u8 + 1),
+                Phase::Sync => TRANSMISSION_HEADER,
+                Phase::Transmission => self.data[self.position],
+            };
+        }
+
+        bytes
+    }
+
+    /// Handle the bytes sent back by each player, None when nothing is plugged in
+    pub fn incoming(&mut self, received: [Option<u8>; DMG07_PLAYERS]) {
+        match self.phase {
+            Phase::Ping => self.receive_ping(received),
+            Phase::Sync => {
+                self.position += 1;
+                if self.position == PING_LEN {
+                    self.position = 0;
+                    self.data = [0; DMG07_PLAYERS * MAX_PACKET_SIZE];
+                    self.phase = Phase::Transmission;
+                }
+            }
+            Phase::Transmission => self.receive_data(received),
+        }
+    }
+
+    fn receive_ping(&mut self, received: [Option<u8>; DMG07_PLAYERS]) {
+        for (player, byte) in received.iter().enumerate() {
+            if self.position < 2 && *byte == Some(ACK) {
+                self.acknowledged |= 1 << (player + self.position * 4);
+            }
+        }
+
+        self.first_player_ping[self.position] = received[0].unwrap_or(0xFF);
+        self.start_requested &= received[0] == Some(START_TRANSMISSION);
+        self.position += 1;
+
+        if self.position == PING_LEN {
+            // Players must acknowledge both the header and the first status byte
+            self.connected = (self.acknowledged & (self.acknowledged >> 4)) & 0x0F;
+            self.acknowledged = 0;
+            self.position = 0;
+
+            // The first player picks the rate and the size after its acknowledgements
+            if self.connected & 0x01 != 0 {
+                self.rate = self.first_player_ping[2];
+                self.size = (self.first_player_ping[3] as usize).clamp(1, MAX_PACKET_SIZE);
+            }
+
+            if self.start_requested {
+                self.phase = Phase::Sync;
+            }
+            self.start_requested = true;
+        }
+    }
+
+    fn receive_data(&mut self, received: [Option<u8>; DMG07_PLAYERS]) {
+        // Players send their data at the start of the packet, what they send after it is ignored
+        if self.position < self.size {
+            for (player, byte) in received.iter().enumerate() {
+                self.next_data[player * self.size + self.position] = byte.unwrap_or(0x00);
+            }
+        }
+
+        self.position += 1;
+
+        if self.position == DMG07_PLAYERS * self.size {
+            self.position = 0;
+            self.data = self.next_data;
+
+            if self.data[..self.size].iter().all(|&byte| byte == RESTART) {
+                self.phase = Phase::Ping;
+                self.connected = 0;
+                self.start_requested = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_and_transmission() {
+        let mut hub = Dmg07::new();
+
+        // Players 1 and 3 answer the pings with a rate of 0x10 and a size of 2
+        for _ in 0..2 {
+            assert_eq!(hub.outgoing()[0], PING_HEADER);
+            for reply in [ACK, ACK, 0x10, 0x02] {
+                hub.incoming([Some(reply), None, Some(reply), None]);
+            }
+        }
+        assert_eq!(hub.rate(), 0x10);
+
+        hub.incoming([Some(START_TRANSMISSION); 4]);
+        assert_eq!(hub.outgoing(), [0x51, 0x52, 0x53, 0x54]);
+        for _ in 0..3 {
+            hub.incoming([Some(START_TRANSMISSION); 4]);
+        }
+
+        for _ in 0..PING_LEN {
+            assert_eq!(hub.outgoing(), [TRANSMISSION_HEADER; 4]);
+            hub.incoming([Some(0x00); 4]);
+        }
+
+        // Every player receives the data of all players during the next packet
+        for position in 0..8 {
+            let byte = position as u8;
+            hub.incoming([
+                Some(0x10 | byte),
+                None,
+                Some(0x30 | byte),
+                Some(0x40 | byte),
+            ]);
+        }
+
+        let mut packet = [0u8; 8];
+        for byte in &mut packet {
+            *byte = hub.outgoing()[1];
+            hub.incoming([Some(RESTART), None, None, None]);
+        }
+        assert_eq!(packet, [0x10, 0x11, 0x00, 0x00, 0x30, 0x31, 0x40, 0x41]);
+
+        // The first player only sent 0xFF, the adapter goes back to the ping phase
+        assert_eq!(hub.outgoing()[0], PING_HEADER);
+    }
+}
@@ -0,0 +1,21 @@
+/// Console being emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardwareModel {
+    /// Original Game Boy
+    Dmg,
+    /// Game Boy Pocket
+    Mgb,
+    /// Super Game Boy
+    Sgb,
+    /// Game Boy Color
+    Cgb,
+    /// Game Boy Advance, running Game Boy Color software
+    Agb,
+}
+
+impl HardwareModel {
+    /// Whether this model has the CGB hardware (palettes, VRAM/WRAM banks, HDMA, double speed)
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, HardwareModel::Cgb | HardwareModel::Agb)
+    }
+}
@@ -0,0 +1,53 @@
+use alloc::vec::Vec;
+
+/// Width of the pictures taken by the Game Boy Camera
+pub const CAMERA_WIDTH: usize = 128;
+
+/// Height of the pictures taken by the Game Boy Camera
+pub const CAMERA_HEIGHT: usize = 112;
+
+/// Provides pictures to the Game Boy Camera
+pub trait ImageSource: Sync + Send {
+    /// Fill `image` with the brightness of every pixel, row by row, 0 being black and 255 white
+    fn capture(&mut self, image: &mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]);
+}
+
+/// Always shows the same grayscale picture, stretched to the size of the camera
+pub struct StaticImage {
+    pixels: Vec<u8>,
+}
+
+impl StaticImage {
+    /// `pixels` is the brightness of every pixel, row by row
+    pub fn new(pixels: &[u8], width: usize, height: usize) -> Self {
+        let mut scaled = Vec::with_capacity(CAMERA_WIDTH * CAMERA_HEIGHT);
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let index = (y * height / CAMERA_HEIGHT) * width + x * width / CAMERA_WIDTH;
+                scaled.push(pixels.get(index).copied().unwrap_or_default());
+            }
+        }
+
+        Self { pixels: scaled }
+    }
+}
+
+impl ImageSource for StaticImage {
+    fn capture(&mut self, image: &mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]) {
+        image.copy_from_slice(&self.pixels);
+    }
+}
+
+/// Horizontal gradient from black to white, so the camera works without any picture
+pub struct TestPattern;
+
+impl ImageSource for TestPattern {
+    fn capture(&mut self, image: &mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]) {
+        for row in image.chunks_exact_mut(CAMERA_WIDTH) {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = (x * 256 / CAMERA_WIDTH) as u8;
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use bitflags::bitflags;
 
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Clone, Copy)]
 pub struct InterruptState {
     pub enable: InterruptReg,
@@ -15,6 +17,19 @@ impl Default for InterruptState {
     }
 }
 
+impl SaveState for InterruptState {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.enable.bits());
+        writer.write_u8(self.status.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enable = InterruptReg::from_bits_truncate(reader.read_u8()?);
+        self.status = InterruptReg::from_bits_truncate(reader.read_u8()?);
+        Ok(())
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct InterruptReg: u8 {
@@ -0,0 +1,154 @@
+use alloc::boxed::Box;
+
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::{IrTransport, NullIrTransport};
+
+/// Both bits need to be set to read the sensor
+const READ_ENABLE: u8 = 0xC0;
+const LED: u8 = 0x01;
+const NO_LIGHT: u8 = 0x02;
+const UNUSED: u8 = 0x3C;
+
+/// Infrared port of the CGB, controlled by the RP register (0xFF56).
+/// Cartridges with their own IR port share the same transport.
+pub struct IrPort {
+    control: u8,
+    receiving: bool,
+    /// LED of the cartridge, None while its IR port isn't mapped
+    cartridge_led: Option<bool>,
+
+    ir_transport: Box<dyn IrTransport>,
+}
+
+impl Default for IrPort {
+    fn default() -> Self {
+        Self {
+            control: 0,
+            receiving: false,
+            cartridge_led: None,
+
+            ir_transport: Box::new(NullIrTransport),
+        }
+    }
+}
+
+impl IrPort {
+    /// Sample the sensor while reading is enabled.
+    /// This is only needed right before the sensor is read, so the transport isn't polled on every cycle.
+    pub fn sample(&mut self) {
+        if self.is_sensing() {
+            self.receiving = self.ir_transport.recv();
+        }
+    }
+
+    pub fn set_ir(&mut self, ir: Box<dyn IrTransport>) {
+        self.ir_transport = ir;
+        self.ir_transport.set_led(self.is_led_on());
+    }
+
+    /// Update the LED of the cartridge, which shines along with the one of the CGB
+    pub fn set_cartridge_led(&mut self, led: Option<bool>) {
+        if led != self.cartridge_led {
+            self.update(|port| port.cartridge_led = led);
+        }
+    }
+
+    /// Light received for the sensor of the cartridge, None while its IR port isn't mapped
+    pub fn cartridge_light(&self) -> Option<bool> {
+        self.cartridge_led.map(|_| self.receiving)
+    }
+
+    pub fn read(&self) -> u8 {
+        let light = self.control & READ_ENABLE == READ_ENABLE && self.receiving;
+        let no_light = if light { 0 } else { NO_LIGHT };
+
+        UNUSED | (self.control & (READ_ENABLE | LED)) | no_light
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.update(|port| port.control = data & (READ_ENABLE | LED));
+    }
+
+    /// Apply a change, then forward the LED to the transport and stop sensing if needed
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let led = self.is_led_on();
+        change(self);
+
+        if self.is_led_on() != led {
+            self.ir_transport.set_led(!led);
+        }
+
+        if !self.is_sensing() {
+            self.receiving = false;
+        }
+    }
+
+    fn is_led_on(&self) -> bool {
+        self.control & LED != 0 || self.cartridge_led == Some(true)
+    }
+
+    fn is_sensing(&self) -> bool {
+        self.control & READ_ENABLE == READ_ENABLE || self.cartridge_led.is_some()
+    }
+}
+
+impl SaveState for IrPort {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.control);
+        writer.write_bool(self.receiving);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = reader.read_u8()? & (READ_ENABLE | LED);
+        self.receiving = reader.read_bool()?;
+        self.ir_transport.set_led(self.is_led_on());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LoopbackIrTransport;
+
+    #[test]
+    fn test_loopback() {
+        let mut ir_port = IrPort::default();
+        ir_port.set_ir(Box::new(LoopbackIrTransport::default()));
+
+        // The sensor can't be read until enabled
+        ir_port.write(LED);
+        ir_port.sample();
+        assert_eq!(ir_port.read(), 0x3F);
+
+        ir_port.write(READ_ENABLE | LED);
+        ir_port.sample();
+        assert_eq!(ir_port.read(), 0xFD);
+
+        ir_port.write(READ_ENABLE);
+        ir_port.sample();
+        assert_eq!(ir_port.read(), 0xFE);
+    }
+
+    #[test]
+    fn test_cartridge_led() {
+        let mut ir_port = IrPort::default();
+        ir_port.set_ir(Box::new(LoopbackIrTransport::default()));
+
+        // The sensor of the cartridge is only sampled while its IR port is mapped
+        assert_eq!(ir_port.cartridge_light(), None);
+        ir_port.set_cartridge_led(Some(true));
+        ir_port.sample();
+        assert_eq!(ir_port.cartridge_light(), Some(true));
+
+        // Both share the same transport, so the CGB sees the LED of the cartridge
+        ir_port.write(READ_ENABLE);
+        ir_port.sample();
+        assert_eq!(ir_port.read(), 0xFC);
+
+        ir_port.set_cartridge_led(Some(false));
+        ir_port.sample();
+        assert_eq!(ir_port.cartridge_light(), Some(false));
+        assert_eq!(ir_port.read(), 0xFE);
+    }
+}
@@ -0,0 +1,33 @@
+pub trait IrTransport: Sync + Send {
+    /// Turn the LED on or off
+    fn set_led(&mut self, on: bool);
+
+    /// Whether the sensor is receiving light
+    fn recv(&mut self) -> bool;
+}
+
+pub struct NullIrTransport;
+
+impl IrTransport for NullIrTransport {
+    fn set_led(&mut self, _on: bool) {}
+
+    fn recv(&mut self) -> bool {
+        false
+    }
+}
+
+/// Receives the light of its own LED, like a mirror in front of the port
+#[derive(Default)]
+pub struct LoopbackIrTransport {
+    led: bool,
+}
+
+impl IrTransport for LoopbackIrTransport {
+    fn set_led(&mut self, on: bool) {
+        self.led = on
+    }
+
+    fn recv(&mut self) -> bool {
+        self.led
+    }
+}
@@ -5,38 +5,114 @@ extern crate alloc;
 #[macro_use]
 pub mod bus; // TODO: Revert pub added for criterion
 
+mod apu;
+mod boot_rom;
 mod cartridge;
 mod cgb_double_speed;
 mod cpu;
 mod dma;
+mod dmg07;
+mod hardware_model;
+mod image_source;
 mod interrupt;
+mod ir_port;
+mod ir_transport;
 mod joypad_state;
 mod ppu;
+mod printer_serial_transport;
 mod rgb_palette;
+mod save_state;
 mod serial;
 mod serial_transport;
+mod sgb;
+mod time_source;
 mod timer_regs;
 pub mod utils;
 
-pub use cartridge::RomParserError;
+pub use cartridge::{
+    CartridgeReadTarget, CartridgeType, CgbFlag, Header, Mapper, MapperFactory, MapperKind,
+    MapperRegistry, RamBanks, RomParserError,
+};
 pub use cgb_double_speed::CgbDoubleSpeed;
 pub use cpu::Cpu;
+pub use dmg07::{Dmg07, DMG07_PLAYERS};
+pub use hardware_model::HardwareModel;
+pub use image_source::*;
 pub use interrupt::{InterruptReg, InterruptState};
+pub use ir_transport::*;
 pub use joypad_state::JoypadState;
-pub use ppu::{Frame, Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+pub use ppu::{CompatibilityPalette, Frame, Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+pub use printer_serial_transport::{PrintedPage, PrinterSerialTransport, PRINT_WIDTH};
+pub use save_state::{SaveState, SaveStateError, StateReader, StateWriter, SAVE_STATE_VERSION};
 pub use serial_transport::*;
+pub use sgb::{SgbFrame, SGB_FRAME_HEIGHT, SGB_FRAME_WIDTH};
+pub use time_source::*;
 
 // TODO: Revert pub added for criterion
+pub use apu::Apu;
+pub use boot_rom::BootRom;
 pub use cartridge::Cartridge;
 pub use dma::*;
+pub use ir_port::IrPort;
 pub use serial::SerialPort;
+pub use sgb::Sgb;
 pub use timer_regs::TimerRegisters;
 
 const WRAM_BANK_SIZE: u16 = 0x1000; // 4KiB
 
+/// Roughly how long the CGB boot ROM logo stays on screen, in T-cycles.
+/// Without a boot ROM, palette button combos are accepted during that time.
+const PALETTE_SELECTION_CYCLES: u32 = 4_194_304 * 2;
+
+/// Length of a frame in T-cycles, used to give up stepping when the CPU never fetches
+const CYCLES_PER_FRAME: u64 = 70224;
+
+/// Number of T-cycles in a second of emulated time
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+
+/// How often the time source is polled, in T-cycles
+const TIME_POLL_CYCLES: u64 = 65536;
+
+/// Why one of the `Emulator::run_*` methods returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A frame was completed and can be retrieved with `Emulator::take_frame`
+    FrameReady,
+    /// The CPU fetched the next instruction
+    InstructionBoundary,
+    /// The predicate passed to `Emulator::run_until` returned true
+    Predicate,
+    /// The CPU didn't fetch any instruction for a whole frame, it is halted or stuck in DMA
+    Halted,
+}
+
+/// Optional settings used to create an `Emulator`
+#[derive(Default)]
+pub struct EmulatorOptions<'a> {
+    /// DMG (256 bytes) or CGB (2304 bytes) boot ROM to run before the cartridge.
+    /// When absent, the emulator starts directly in the state left by the boot ROM.
+    pub boot_rom: Option<&'a [u8]>,
+
+    /// Console to emulate. When absent, it is inferred from the boot ROM, or defaults to CGB.
+    pub model: Option<HardwareModel>,
+
+    /// Mapper to use instead of the one from the header, for unlicensed cartridges that aren't detected.
+    pub mapper: Option<MapperKind>,
+
+    /// Mappers provided by the frontend, tried before the built-in ones
+    pub custom_mappers: MapperRegistry,
+
+    /// Time used by cartridge clocks. Defaults to `EmulatedTime` starting at the UNIX epoch.
+    /// It is needed right away to count the time that passed since the save file was written.
+    pub time_source: Option<alloc::boxed::Box<dyn TimeSource>>,
+}
+
 pub struct Emulator {
+    model: HardwareModel,
+
     // == Cartridge Related Hardware== //
     cartridge: Cartridge,
+    boot_rom: BootRom,
 
     // == CPU Related Hardware == //
     cpu: Cpu,
@@ -55,27 +131,109 @@ pub struct Emulator {
     ppu: Ppu,
     cgb_mode: bool,
 
+    // == APU Related Hardware == //
+    apu: Apu,
+
     // == IP Related Hardware == //
     serial_port: SerialPort,
+    ir_port: IrPort,
 
     // == IO Hardware ==
     joypad_state: JoypadState,
     joypad_register: u8,
 
+    // == Super Game Boy == //
+    sgb: Option<Sgb>,
+
     // == Emulation Specific Data == //
     clock_count: u8,
+    cycle_count: u64,
+    palette_selection_cycles: u32,
+    frame: Option<Frame>,
+    time_source: alloc::boxed::Box<dyn TimeSource>,
+
+    /// T-cycles the rumble motor ran since the start of the frame
+    rumble_cycles: u64,
+    rumble_frame_start: u64,
+    rumble: f32,
 }
 
 impl Emulator {
     pub fn new(rom: &[u8], save_data: Option<&[u8]>) -> Result<Self, RomParserError> {
-        let cartridge = Cartridge::load(rom, save_data)?;
-        let cgb_mode = cartridge.is_cgb();
+        Self::with_options(rom, save_data, Default::default())
+    }
+
+    pub fn with_options(
+        rom: &[u8],
+        save_data: Option<&[u8]>,
+        options: EmulatorOptions,
+    ) -> Result<Self, RomParserError> {
+        let cartridge =
+            Cartridge::load_with_mapper(rom, save_data, options.mapper, &options.custom_mappers)?;
+
+        let boot_rom = match options.boot_rom {
+            Some(data) => BootRom::new(data)?,
+            None => Default::default(),
+        };
+
+        let model = match options.model {
+            Some(model) => model,
+            None if boot_rom.is_mapped() && !boot_rom.is_cgb() => HardwareModel::Dmg,
+            None => HardwareModel::Cgb,
+        };
+
+        if boot_rom.is_mapped() && boot_rom.is_cgb() != model.is_cgb() {
+            return Err(RomParserError::InvalidBootRom);
+        }
+
+        // The CGB boot ROM always starts in CGB mode and switches to DMG mode itself if needed
+        let cgb_mode = model.is_cgb() && (boot_rom.is_mapped() || cartridge.is_cgb());
+
         let mut ppu = Ppu::new(cgb_mode);
-        ppu.set_dmg_colorized_palette(&cartridge.header.title);
+        if model == HardwareModel::Sgb {
+            ppu.set_sgb_mode();
+        }
+
+        if !model.is_cgb() {
+            ppu.set_dmg_grayscale_palette();
+        } else if !cgb_mode {
+            // Do what the CGB boot ROM does for DMG games
+            ppu.set_compatibility_mode();
+            ppu.set_dmg_colorized_palette(&cartridge.header.title);
+        }
+
+        // Without a boot ROM, we still let the player pick a palette with a button combo
+        let palette_selection_cycles = if ppu.is_compatibility_mode() {
+            PALETTE_SELECTION_CYCLES
+        } else {
+            0
+        };
+
+        // The SGB BIOS only accepts commands from games with both the SGB flag and the new licensee code
+        let sgb = (model == HardwareModel::Sgb).then(|| {
+            Sgb::new(cartridge.header.sgb_flag && cartridge.header.old_licensee_code == 0x33)
+        });
+
+        let mut apu = Apu::default();
+        let cpu = if boot_rom.is_mapped() {
+            // Start from a cold boot and let the boot ROM initialize the hardware
+            ppu.clear_palettes();
+            apu.write(0xFF26, 0x00);
+            Cpu::power_on()
+        } else {
+            Cpu::after_boot(
+                model,
+                cgb_mode,
+                cartridge.header.header_checksum,
+                cartridge.title_checksum(),
+            )
+        };
 
-        let emulator = Self {
+        let mut emulator = Self {
+            model,
             cartridge,
-            cpu: Default::default(),
+            boot_rom,
+            cpu,
             interrupts: Default::default(),
             double_speed: Default::default(),
             timer_registers: Default::default(),
@@ -89,19 +247,201 @@ impl Emulator {
             ppu,
             cgb_mode,
 
+            apu,
+
             serial_port: Default::default(),
+            ir_port: Default::default(),
 
             joypad_state: Default::default(),
             joypad_register: Default::default(),
 
+            sgb,
+
             clock_count: 0,
+            cycle_count: 0,
+            palette_selection_cycles,
+            frame: None,
+            time_source: options
+                .time_source
+                .unwrap_or_else(|| alloc::boxed::Box::new(EmulatedTime::new(0))),
+            rumble_cycles: 0,
+            rumble_frame_start: 0,
+            rumble: 0.0,
         };
+        emulator.update_time();
 
         Ok(emulator)
     }
 
+    /// Run a single T-cycle. Returns a frame if one is available.
     pub fn clock(&mut self) -> Option<Frame> {
+        self.tick();
+        self.frame.take()
+    }
+
+    /// Run until the next frame is complete and return it.
+    /// A frame completed by an earlier run and not taken yet is dropped.
+    pub fn run_frame(&mut self) -> Frame {
+        self.frame = None;
+
+        loop {
+            self.advance(u64::MAX);
+
+            if let Some(frame) = self.frame.take() {
+                break frame;
+            }
+        }
+    }
+
+    /// Run until the CPU fetches the next instruction.
+    /// Gives up after a frame if the CPU is halted.
+    pub fn step_instruction(&mut self) -> StopReason {
+        let mut remaining = CYCLES_PER_FRAME;
+        while remaining > 0 {
+            let (cycles, fetched) = self.advance(remaining);
+            if fetched {
+                return StopReason::InstructionBoundary;
+            }
+
+            remaining -= cycles;
+        }
+
+        StopReason::Halted
+    }
+
+    /// Run for the specified number of T-cycles.
+    /// The last frame completed in that time can be retrieved with `take_frame`.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            remaining -= self.advance(remaining).0;
+        }
+    }
+
+    /// Run until a frame is complete, or until `predicate` returns true.
+    /// The predicate is checked every time the CPU fetches a new instruction.
+    pub fn run_until(&mut self, mut predicate: impl FnMut(&Self) -> bool) -> StopReason {
+        loop {
+            let (_, fetched) = self.advance(u64::MAX);
+
+            if self.frame.is_some() {
+                return StopReason::FrameReady;
+            }
+
+            if fetched && predicate(self) {
+                return StopReason::Predicate;
+            }
+        }
+    }
+
+    /// Take the last completed frame, if it wasn't already
+    pub fn take_frame(&mut self) -> Option<Frame> {
+        self.frame.take()
+    }
+
+    /// Number of T-cycles run since the emulator was created
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+
+    /// Run up to `max_cycles` T-cycles, skipping straight to the next event of any component.
+    /// Returns the number of T-cycles run, and whether the CPU fetched a new instruction.
+    fn advance(&mut self, max_cycles: u64) -> (u64, bool) {
+        let double_speed = self.double_speed.contains(CgbDoubleSpeed::ENABLED);
+        let cpu_cycle = if double_speed { 2 } else { 4 };
+
+        // The PPU has something to do on every T-cycle
+        if !self.clock_count.is_multiple_of(cpu_cycle)
+            || max_cycles < cpu_cycle as u64
+            || self.ppu.idle_cycles() < cpu_cycle as u32
+        {
+            return (1, self.tick());
+        }
+
+        // Only skip whole CPU cycles, in both speed modes
+        let idle = (self.idle_cycles() as u64).min(max_cycles) & !3;
+
+        if idle != 0 {
+            self.skip(idle as u32);
+            (idle, false)
+        } else {
+            // The PPU only counts cycles until the CPU runs, so the whole CPU cycle can run at once
+            (cpu_cycle as u64, self.tick_cpu_cycle(cpu_cycle))
+        }
+    }
+
+    /// Number of T-cycles before the next event of any component.
+    /// The CPU only touches the rest of the hardware on the first and last cycles of its instructions.
+    fn idle_cycles(&self) -> u32 {
+        let dma =
+            self.oam_dma.cycle.is_some() || (self.cgb_mode && self.hdma.is_currently_in_hdma());
+        if self.clock_count != 0 || dma {
+            return 0;
+        }
+
+        let double_speed = self.double_speed.contains(CgbDoubleSpeed::ENABLED);
+        let cpu_cycle = if double_speed { 2 } else { 4 };
+
+        // Most of the time the CPU is running an instruction, check it first
+        let pending_interrupts = self.interrupts.enable & self.interrupts.status;
+        let interrupt_pending = !(pending_interrupts & !InterruptReg::UNUSED).is_empty();
+        let cpu = self.cpu.idle_cycles(interrupt_pending);
+        if cpu == 0 {
+            return 0;
+        }
+
+        // The sound from the cartridge changes every cycle
+        if self.cartridge.audio_output(self.cycle_count).is_some() {
+            return 0;
+        }
+
+        let div = self.timer_registers.get_div();
+        let deadlines = [
+            cpu.saturating_mul(cpu_cycle),
+            self.ppu.idle_cycles(),
+            self.timer_registers.idle_cycles().saturating_mul(cpu_cycle),
+            self.serial_port.idle_cycles().saturating_mul(cpu_cycle),
+            self.apu
+                .idle_cycles(div, double_speed)
+                .saturating_mul(cpu_cycle),
+        ];
+
+        deadlines.into_iter().min().unwrap_or_default()
+    }
+
+    /// Skip cycles returned by `idle_cycles`, as if `tick` was called that many times
+    fn skip(&mut self, cycles: u32) {
+        let double_speed = self.double_speed.contains(CgbDoubleSpeed::ENABLED);
+        let cpu_cycle = if double_speed { 2 } else { 4 };
+
+        self.cycle_count += cycles as u64;
+        self.palette_selection_cycles = self.palette_selection_cycles.saturating_sub(cycles);
+
+        self.cpu.skip(cycles / cpu_cycle);
+        self.ppu.skip(cycles);
+        self.timer_registers.skip(cycles / cpu_cycle);
+        self.serial_port.skip(cycles / cpu_cycle);
+        self.apu.skip(
+            cycles,
+            cpu_cycle as u8,
+            self.timer_registers.get_div(),
+            double_speed,
+        );
+        self.cartridge.clock(cycles);
+
+        if self.cartridge.rumble() {
+            self.rumble_cycles += cycles as u64;
+        }
+
+        self.poll_time(cycles);
+    }
+
+    /// Run a single T-cycle. Returns true if the CPU fetched a new instruction.
+    fn tick(&mut self) -> bool {
+        let mut fetched = false;
+        self.cycle_count += 1;
         self.clock_count += 1;
+        self.palette_selection_cycles = self.palette_selection_cycles.saturating_sub(1);
 
         // clock_count is at ~4MHz
         // PPU is clocked at ~4MHz
@@ -112,30 +452,305 @@ impl Emulator {
         // This means we clock it every 2 or 4 cycles
         let double_speed = self.double_speed.contains(CgbDoubleSpeed::ENABLED);
         if (double_speed && self.clock_count == 2) || self.clock_count == 4 {
-            let mut cpu_bus = borrow_cpu_bus!(self);
-            self.cpu.clock(&mut cpu_bus);
+            fetched = self.clock_cpu(double_speed);
+        };
+
+        self.take_ready_frame();
+        self.poll_time(1);
+
+        fetched
+    }
+
+    /// Run a whole CPU cycle while the PPU is idle, as if `tick` was called that many times.
+    /// Returns true if the CPU fetched a new instruction.
+    fn tick_cpu_cycle(&mut self, cpu_cycle: u8) -> bool {
+        self.cycle_count += cpu_cycle as u64;
+        self.clock_count += cpu_cycle;
+        self.palette_selection_cycles = self
+            .palette_selection_cycles
+            .saturating_sub(cpu_cycle as u32);
+
+        self.ppu.skip(cpu_cycle as u32);
+        let fetched = self.clock_cpu(cpu_cycle == 2);
 
-            if self.clock_count == 4 {
-                self.clock_count = 0;
+        self.take_ready_frame();
+        self.poll_time(cpu_cycle as u32);
+
+        fetched
+    }
+
+    /// Clock the CPU and the hardware running at its speed. Returns true if the CPU fetched a new instruction.
+    fn clock_cpu(&mut self, double_speed: bool) -> bool {
+        let mut cpu_bus = borrow_cpu_bus!(self);
+        let fetched = self.cpu.clock(&mut cpu_bus);
+
+        // The APU runs at the same speed in double speed mode, so it gets half the T-cycles
+        let cycles = if double_speed { 2 } else { 4 };
+        self.apu
+            .set_vin(self.cartridge.audio_output(self.cycle_count).unwrap_or(0.0));
+        self.cartridge.clock(cycles as u32);
+        if self.cartridge.rumble() {
+            self.rumble_cycles += cycles as u64;
+        }
+        self.apu
+            .clock(cycles, self.timer_registers.get_div(), double_speed);
+
+        if self.clock_count == 4 {
+            self.clock_count = 0;
+        }
+
+        fetched
+    }
+
+    /// Keep the frame until it is taken
+    fn take_ready_frame(&mut self) {
+        if let Some(mut frame) = self.ppu.ready_frame() {
+            if let Some(sgb) = &mut self.sgb {
+                sgb.process_frame(&mut frame, self.ppu.sgb_shades());
             }
+
+            self.frame = Some(frame);
+            self.update_rumble();
+        }
+    }
+
+    /// Poll the time if the last `cycles` T-cycles went over a polling point
+    fn poll_time(&mut self, cycles: u32) {
+        if (self.cycle_count - cycles as u64) / TIME_POLL_CYCLES
+            != self.cycle_count / TIME_POLL_CYCLES
+        {
+            self.update_time();
+        }
+    }
+
+    /// Average the motor over the frame, as games drive it in pulses to control its strength
+    fn update_rumble(&mut self) {
+        let frame_cycles = self.cycle_count - self.rumble_frame_start;
+        self.rumble = if frame_cycles > 0 {
+            (self.rumble_cycles as f32 / frame_cycles as f32).min(1.0)
+        } else {
+            0.0
         };
 
-        // Return a frame if available
-        self.ppu.ready_frame()
+        self.rumble_cycles = 0;
+        self.rumble_frame_start = self.cycle_count;
+    }
+
+    /// Let the cartridge clock catch up with the time source
+    fn update_time(&mut self) {
+        let now = self.time_source.now(self.cycle_count / CYCLES_PER_SECOND);
+        self.cartridge.set_time(now);
     }
 
     pub fn set_serial(&mut self, serial: alloc::boxed::Box<dyn SerialTransport>) {
         self.serial_port.set_serial(serial)
     }
 
+    /// Replace what the infrared port of the CGB communicates with
+    pub fn set_ir(&mut self, ir: alloc::boxed::Box<dyn IrTransport>) {
+        self.ir_port.set_ir(ir)
+    }
+
     pub fn set_joypad(&mut self, state: JoypadState) {
-        self.joypad_state = state
+        self.joypad_state = state;
+
+        if self.palette_selection_cycles > 0 {
+            if let Some(palette) = CompatibilityPalette::from_buttons(state) {
+                self.ppu.set_compatibility_palette(palette);
+            }
+        }
+    }
+
+    /// Replace the pictures seen by the Game Boy Camera. Defaults to `TestPattern`.
+    pub fn set_image_source(&mut self, image_source: alloc::boxed::Box<dyn ImageSource>) {
+        self.cartridge.set_image_source(image_source);
+    }
+
+    /// Tilt the cartridge, for games with an accelerometer.
+    /// `x` is positive when tilted right and `y` when tilted towards the player, 1.0 being 90°.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.cartridge.set_tilt(x, y);
+    }
+
+    /// Strength of the rumble motor during the last frame, from 0.0 to 1.0
+    pub fn rumble(&self) -> f32 {
+        self.rumble
+    }
+
+    /// Size of the frames to show, enlarged on the SGB to fit its border
+    pub fn screen_size(&self) -> (usize, usize) {
+        if self.sgb.is_some() {
+            (SGB_FRAME_WIDTH, SGB_FRAME_HEIGHT)
+        } else {
+            (FRAME_WIDTH, FRAME_HEIGHT)
+        }
+    }
+
+    /// Draw the SGB border around a frame returned by the emulator.
+    /// Returns None on other models, which have no border.
+    pub fn sgb_frame(&self, frame: &Frame) -> Option<SgbFrame> {
+        self.sgb.as_ref().map(|sgb| sgb.render_border(frame))
+    }
+
+    /// Change the colors of a DMG game running on a CGB.
+    /// Does nothing for CGB games and on models without a CGB.
+    pub fn set_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        self.ppu.set_compatibility_palette(palette)
     }
 
-    pub fn get_save_data(&self) -> Option<&[u8]> {
+    /// Set the rate at which audio samples are generated, in Hz.
+    /// Audio is not generated until this is called with a non-zero rate.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate)
+    }
+
+    /// Take the audio samples generated since the last call,
+    /// as interleaved stereo (left, right) values between -1.0 and 1.0
+    pub fn drain_audio_samples(&mut self) -> alloc::vec::Vec<f32> {
+        self.apu.drain_samples()
+    }
+
+    /// Battery-backed data of the cartridge, in the format used by most emulators
+    pub fn get_save_data(&self) -> Option<alloc::vec::Vec<u8>> {
         self.cartridge.get_save_data()
     }
 
+    /// Serialize the whole state of the emulated hardware.
+    /// The serial and IR links, the joypad input and the frontend are not part of the state.
+    pub fn save_state(&self) -> alloc::vec::Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_header(&self.cartridge.rom_id());
+        writer.write_bool(self.sgb.is_some());
+
+        self.boot_rom.save_state(&mut writer);
+        self.cpu.save_state(&mut writer);
+        writer.write_bytes(&self.wram);
+        writer.write_u8(self.wram_bank);
+        writer.write_bytes(&self.hram);
+        self.interrupts.save_state(&mut writer);
+        self.double_speed.save_state(&mut writer);
+        self.oam_dma.save_state(&mut writer);
+        self.hdma.save_state(&mut writer);
+        self.timer_registers.save_state(&mut writer);
+
+        self.ppu.save_state(&mut writer);
+        writer.write_bool(self.cgb_mode);
+
+        self.apu.save_state(&mut writer);
+
+        self.serial_port.save_state(&mut writer);
+        self.ir_port.save_state(&mut writer);
+        writer.write_u8(self.joypad_register);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(&mut writer);
+        }
+        writer.write_u8(self.clock_count);
+        writer.write_u64(self.cycle_count);
+
+        self.cartridge.save_state(&mut writer);
+
+        writer.into_inner()
+    }
+
+    /// Restore a state created by `save_state`.
+    /// On error, the emulator is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data, &self.cartridge.rom_id())?;
+
+        // Keep the current state around in case the new one is corrupted halfway through
+        let backup = self.save_state();
+
+        let result = self.read_state(&mut reader).and_then(|_| {
+            // Trailing data means the layout doesn't match what we expect
+            if reader.is_empty() {
+                Ok(())
+            } else {
+                Err(SaveStateError::InvalidData)
+            }
+        });
+
+        if result.is_err() {
+            let mut backup_reader = StateReader::new(&backup, &self.cartridge.rom_id())
+                .expect("the backup was just created");
+            self.read_state(&mut backup_reader)
+                .expect("the backup was just created");
+        }
+
+        result
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        // Versions before 10 didn't tell whether the SGB was enabled
+        if reader.version() >= 10 && reader.read_bool()? != self.sgb.is_some() {
+            return Err(SaveStateError::SgbMismatch);
+        }
+
+        // Versions before 3 didn't support boot ROMs
+        if reader.version() >= 3 {
+            self.boot_rom.load_state(reader)?;
+        } else {
+            self.boot_rom.unmap();
+        }
+        self.cpu.load_state(reader)?;
+        reader.read_bytes(&mut self.wram)?;
+        self.wram_bank = reader.read_u8()?;
+        reader.read_bytes(&mut self.hram)?;
+        self.interrupts.load_state(reader)?;
+        self.double_speed.load_state(reader)?;
+        self.oam_dma.load_state(reader)?;
+        self.hdma.load_state(reader)?;
+        self.timer_registers.load_state(reader)?;
+
+        self.ppu.load_state(reader)?;
+        self.cgb_mode = reader.read_bool()?;
+
+        // Version 1 had no APU
+        if reader.version() >= 2 {
+            self.apu.load_state(reader)?;
+        } else {
+            self.apu.reset();
+        }
+
+        self.serial_port.load_state(reader)?;
+
+        // Versions before 8 had no IR port
+        if reader.version() >= 8 {
+            self.ir_port.load_state(reader)?;
+        } else {
+            self.ir_port.write(0);
+        }
+
+        self.joypad_register = reader.read_u8()?;
+
+        // Versions before 9 didn't support the SGB
+        if let Some(sgb) = &mut self.sgb {
+            if reader.version() >= 9 {
+                sgb.load_state(reader)?;
+            } else {
+                sgb.reset();
+            }
+        }
+
+        self.clock_count = reader.read_u8()?;
+
+        // Versions before 5 didn't count cycles
+        self.cycle_count = if reader.version() >= 5 {
+            reader.read_u64()?
+        } else {
+            0
+        };
+
+        self.cartridge.load_state(reader)?;
+
+        // The cartridge shares the IR transport with the CGB
+        self.ir_port.set_cartridge_led(self.cartridge.ir_led());
+        Ok(())
+    }
+
+    pub fn model(&self) -> HardwareModel {
+        self.model
+    }
+
     #[cfg(feature = "debugger")]
     pub fn disassemble(
         &mut self,
@@ -146,19 +761,23 @@ impl Emulator {
         crate::cpu::debugger::disassemble(&mut bus)
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn mapper_registers(&self) -> alloc::vec::Vec<(&'static str, u32)> {
+        self.cartridge.mapper_registers()
+    }
+
     #[cfg(feature = "debugger")]
     pub fn mem_dump(&mut self, start: u16, end: u16) -> alloc::vec::Vec<u8> {
         let mut data = alloc::vec::Vec::new();
 
         for addr in start..=end {
             let bus = borrow_cpu_bus!(self);
-            data.push(bus.read(addr));
+            data.push(bus.read_without_dma_check(addr, false));
         }
 
         data
     }
 
-    #[cfg(feature = "debugger")]
     pub fn cpu(&self) -> &Cpu {
         &self.cpu
     }
@@ -174,3 +793,150 @@ fn test() {
         emu.clock();
     }
 }
+
+#[test]
+fn test_boot_rom() {
+    let mut rom = [0u8; 0x150];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+    rom[0x14d] = 231;
+
+    let mut boot_rom = [0u8; 0x100];
+    boot_rom[0x00..0x07].copy_from_slice(&[
+        0x31, 0xFE, 0xFF, // ld sp, 0xFFFE
+        0x3E, 0x42, // ld a, 0x42
+        0xE0, 0x80, // ldh (0x80), a
+    ]);
+    boot_rom[0xFC..0x100].copy_from_slice(&[
+        0x3E, 0x01, // ld a, 0x01
+        0xE0, 0x50, // ldh (0x50), a
+    ]);
+
+    let options = EmulatorOptions {
+        boot_rom: Some(&boot_rom),
+        ..Default::default()
+    };
+    let mut emu = Emulator::with_options(&rom, None, options).unwrap();
+    assert_eq!(emu.model(), HardwareModel::Dmg);
+    assert!(!emu.cgb_mode);
+    assert_eq!(borrow_cpu_bus!(emu).read(0x0000), 0x31);
+
+    for _ in 0..0x1000 {
+        emu.clock();
+    }
+
+    assert!(!emu.boot_rom.is_mapped());
+    assert_eq!(emu.hram[0], 0x42);
+    assert_eq!(borrow_cpu_bus!(emu).read(0x0000), 0x00);
+    assert!((0x100..0x103).contains(&emu.cpu.pc));
+}
+
+#[test]
+fn test_compatibility_palette_buttons() {
+    assert_eq!(
+        CompatibilityPalette::from_buttons(JoypadState::UP),
+        Some(CompatibilityPalette::Brown)
+    );
+    assert_eq!(
+        CompatibilityPalette::from_buttons(JoypadState::LEFT | JoypadState::B),
+        Some(CompatibilityPalette::Grayscale)
+    );
+    assert_eq!(
+        CompatibilityPalette::from_buttons(JoypadState::RIGHT | JoypadState::A),
+        Some(CompatibilityPalette::DarkGreen)
+    );
+    assert_eq!(CompatibilityPalette::from_buttons(JoypadState::A), None);
+}
+
+#[test]
+fn test_run_apis() {
+    let mut rom = [0u8; 0x150];
+    rom[0x100..0x105].copy_from_slice(&[
+        0x00, // nop
+        0x3C, // inc a
+        0xC3, 0x01, 0x01, // jp 0x0101
+    ]);
+    rom[0x14d] = 231;
+    let mut emu = Emulator::new(&rom, None).unwrap();
+
+    assert_eq!(emu.step_instruction(), StopReason::InstructionBoundary);
+    assert_eq!(emu.cpu().instruction_address, 0x100);
+    assert_eq!(emu.step_instruction(), StopReason::InstructionBoundary);
+    assert_eq!(emu.cpu().instruction_address, 0x101);
+    assert_eq!(emu.step_instruction(), StopReason::InstructionBoundary);
+    assert_eq!(emu.cpu().instruction_address, 0x102);
+    assert_eq!(emu.cycle_count(), 4 * 3);
+
+    let a = emu.cpu().a;
+    assert_eq!(
+        emu.run_until(|emu| emu.cpu().a == a.wrapping_add(3)),
+        StopReason::Predicate
+    );
+
+    // The loop never ends, so we always reach the end of the frame
+    assert_eq!(emu.run_until(|_| false), StopReason::FrameReady);
+    assert!(emu.take_frame().is_some());
+    assert!(emu.take_frame().is_none());
+
+    let cycles = emu.cycle_count();
+    emu.run_frame();
+    assert_eq!(emu.cycle_count() - cycles, CYCLES_PER_FRAME);
+}
+
+#[test]
+fn test_time_source() {
+    let mut rom = [0u8; 0x150];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+    rom[0x147] = 0x0F; // MBC3 + Timer + Battery
+    rom[0x14d] = 216;
+    let mut emu = Emulator::new(&rom, None).unwrap();
+
+    // Emulated time only advances with the emulation
+    emu.run_cycles(CYCLES_PER_SECOND * 2);
+    assert_eq!(read_rtc(&mut emu, 0x08), 2);
+}
+
+#[test]
+fn test_time_source_save() {
+    let mut rom = [0u8; 0x150];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+    rom[0x147] = 0x10; // MBC3 + Timer + RAM + Battery
+    rom[0x149] = 0x02;
+    rom[0x14d] = 213;
+
+    let options = |start, offset| EmulatorOptions {
+        time_source: Some(alloc::boxed::Box::new(OffsetTime::new(
+            alloc::boxed::Box::new(EmulatedTime::new(start)),
+            offset,
+        ))),
+        ..Default::default()
+    };
+
+    // A new cartridge starts its clock at 0
+    let mut emu = Emulator::with_options(&rom, None, options(1_700_000_000, 0)).unwrap();
+    let save = emu.get_save_data().unwrap();
+    assert_eq!(read_rtc(&mut emu, 0x08), 0);
+    assert_eq!(read_rtc(&mut emu, 0x0C), 0);
+
+    // An offset moves the clock forward, on top of the 10 seconds since the save
+    let mut emu = Emulator::with_options(&rom, Some(&save), options(1_700_000_010, 60)).unwrap();
+    let save = emu.get_save_data().unwrap();
+    assert_eq!(read_rtc(&mut emu, 0x08), 10);
+    assert_eq!(read_rtc(&mut emu, 0x09), 1);
+
+    // The save doesn't keep the offset, so passing it again only counts the 10 seconds since
+    let mut emu = Emulator::with_options(&rom, Some(&save), options(1_700_000_020, 60)).unwrap();
+    assert_eq!(read_rtc(&mut emu, 0x08), 20);
+    assert_eq!(read_rtc(&mut emu, 0x09), 1);
+    assert_eq!(read_rtc(&mut emu, 0x0A), 0);
+    assert_eq!(read_rtc(&mut emu, 0x0B), 0);
+    assert_eq!(read_rtc(&mut emu, 0x0C), 0);
+}
+
+#[cfg(test)]
+fn read_rtc(emu: &mut Emulator, register: u8) -> u8 {
+    emu.cartridge.write(0x0000, 0x0A);
+    emu.cartridge.write(0x6000, 0x00);
+    emu.cartridge.write(0x6000, 0x01);
+    emu.cartridge.write(0x4000, register);
+    emu.cartridge.read(0xA000)
+}
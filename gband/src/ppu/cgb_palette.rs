@@ -1,4 +1,5 @@
 use super::fifo_mode::FifoMode;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
 pub struct CgbPalette {
     pub data: [u8; 0x40],
@@ -59,25 +60,52 @@ impl CgbPalette {
         // Note: There is no autoincrement on read
     }
 
-    pub fn get_rgb(&self, palette_index: usize, color_index: usize) -> [u8; 3] {
-        let mut pixel = [0u8; 3];
+    /// Store an RGB888 color, truncated to RGB555
+    pub fn set_rgb(&mut self, palette_index: usize, color_index: usize, rgb: [u8; 3]) {
+        let color555 =
+            (rgb[0] as u16 >> 3) | ((rgb[1] as u16 >> 3) << 5) | ((rgb[2] as u16 >> 3) << 10);
+
+        self.data[(palette_index << 3) | (color_index << 1)] = color555 as u8;
+        self.data[(palette_index << 3) | (color_index << 1) | 1] = (color555 >> 8) as u8;
+    }
 
+    pub fn get_rgb(&self, palette_index: usize, color_index: usize) -> [u8; 3] {
         let lo = self.data[(palette_index << 3) | (color_index << 1)] as u16;
         let hi = self.data[(palette_index << 3) | (color_index << 1) | 1] as u16;
 
-        let mut color555 = (hi << 8) | lo;
+        rgb555_to_rgb888((hi << 8) | lo)
+    }
+}
 
-        let r555 = (color555 & 0x1f) as u8;
-        pixel[0] = (r555 << 3) | (r555 >> 2);
-        color555 >>= 5;
+/// Expand a color to 8 bits per channel, repeating the top bits so white stays white
+pub fn rgb555_to_rgb888(mut color555: u16) -> [u8; 3] {
+    let mut pixel = [0u8; 3];
 
-        let g555 = (color555 & 0x1f) as u8;
-        pixel[1] = (g555 << 3) | (g555 >> 2);
-        color555 >>= 5;
+    let r555 = (color555 & 0x1f) as u8;
+    pixel[0] = (r555 << 3) | (r555 >> 2);
+    color555 >>= 5;
 
-        let b555 = (color555 & 0x1f) as u8;
-        pixel[2] = (b555 << 3) | (b555 >> 2);
+    let g555 = (color555 & 0x1f) as u8;
+    pixel[1] = (g555 << 3) | (g555 >> 2);
+    color555 >>= 5;
+
+    let b555 = (color555 & 0x1f) as u8;
+    pixel[2] = (b555 << 3) | (b555 >> 2);
+
+    pixel
+}
+
+impl SaveState for CgbPalette {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_bool(self.autoincrement);
+        writer.write_u8(self.index as u8);
+    }
 
-        pixel
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.data)?;
+        self.autoincrement = reader.read_bool()?;
+        self.index = (reader.read_u8()? & 0x3F) as usize;
+        Ok(())
     }
 }
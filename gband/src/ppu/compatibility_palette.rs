@@ -0,0 +1,83 @@
+use num_enum::TryFromPrimitive;
+
+use super::palette_table;
+use crate::JoypadState;
+
+/// Palettes the CGB boot ROM lets the player pick for DMG games,
+/// by holding a direction and optionally A or B during the logo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum CompatibilityPalette {
+    /// Up
+    Brown,
+    /// Up + A
+    Red,
+    /// Up + B
+    DarkBrown,
+    /// Left
+    Blue,
+    /// Left + A
+    DarkBlue,
+    /// Left + B
+    Grayscale,
+    /// Down
+    PaleYellow,
+    /// Down + A
+    Orange,
+    /// Down + B
+    Yellow,
+    /// Right
+    Green,
+    /// Right + A
+    DarkGreen,
+    /// Right + B
+    Inverted,
+}
+
+impl CompatibilityPalette {
+    pub const COUNT: u8 = 12;
+
+    /// Returns the palette selected by this button combination, if any
+    pub fn from_buttons(state: JoypadState) -> Option<Self> {
+        let direction = if state.contains(JoypadState::UP) {
+            CompatibilityPalette::Brown
+        } else if state.contains(JoypadState::LEFT) {
+            CompatibilityPalette::Blue
+        } else if state.contains(JoypadState::DOWN) {
+            CompatibilityPalette::PaleYellow
+        } else if state.contains(JoypadState::RIGHT) {
+            CompatibilityPalette::Green
+        } else {
+            return None;
+        };
+
+        let offset = if state.contains(JoypadState::A) {
+            1
+        } else if state.contains(JoypadState::B) {
+            2
+        } else {
+            0
+        };
+
+        Self::try_from(direction as u8 + offset).ok()
+    }
+
+    pub(super) fn colors(&self) -> [[[u8; 3]; 4]; 3] {
+        let (table, entry) = match self {
+            CompatibilityPalette::Brown => (0, 0x12),
+            CompatibilityPalette::Red => (5, 0x10),
+            CompatibilityPalette::DarkBrown => (5, 0x19),
+            CompatibilityPalette::Blue => (5, 0x18),
+            CompatibilityPalette::DarkBlue => (5, 0x0D),
+            CompatibilityPalette::Grayscale => (0, 0x16),
+            CompatibilityPalette::PaleYellow => (0, 0x17),
+            CompatibilityPalette::Orange => (0, 0x07),
+            CompatibilityPalette::Yellow => (5, 0x1A),
+            CompatibilityPalette::Green => (0, 0x05),
+            CompatibilityPalette::DarkGreen => (5, 0x1C),
+            CompatibilityPalette::Inverted => (0, 0x13),
+        };
+
+        palette_table::palette_fill_from_table_entry(table, entry)
+    }
+}
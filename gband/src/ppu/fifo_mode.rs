@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Clone, Copy)]
 pub enum FifoMode {
     HBlank,
@@ -79,3 +81,90 @@ impl From<FifoMode> for u8 {
         }
     }
 }
+
+impl SaveState for FifoMode {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8((*self).into());
+
+        match self {
+            FifoMode::HBlank | FifoMode::VBlank => {}
+            FifoMode::OamScan(state) => {
+                writer.write_u8(state.oam_pointer as u8);
+                writer.write_u8(state.secondary_oam_pointer as u8);
+                writer.write_bool(state.is_visible);
+            }
+            FifoMode::Drawing(state) => {
+                writer.write_u8(state.pixel_fetcher.into());
+                writer.write_u8(state.cycle);
+                writer.write_u8(state.fetcher_x);
+                writer.write_bool(state.is_window);
+                writer.write_bool(state.is_sprite);
+                writer.write_u8(state.sprite_idx);
+                writer.write_u8(state.tile_idx);
+                writer.write_u8(state.tile_attr);
+                for pixel in state.buffer {
+                    writer.write_u16(pixel);
+                }
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = match reader.read_u8()? {
+            0 => FifoMode::HBlank,
+            1 => FifoMode::VBlank,
+            2 => FifoMode::OamScan(OamScanState {
+                oam_pointer: reader.read_u8()? as usize,
+                secondary_oam_pointer: reader.read_u8()? as usize,
+                is_visible: reader.read_bool()?,
+            }),
+            3 => {
+                let mut state = DrawingState {
+                    pixel_fetcher: PixelFetcherState::try_from(reader.read_u8()?)?,
+                    cycle: reader.read_u8()?,
+                    fetcher_x: reader.read_u8()?,
+                    is_window: reader.read_bool()?,
+                    is_sprite: reader.read_bool()?,
+                    sprite_idx: reader.read_u8()?,
+                    tile_idx: reader.read_u8()?,
+                    tile_attr: reader.read_u8()?,
+                    buffer: Default::default(),
+                };
+
+                for pixel in &mut state.buffer {
+                    *pixel = reader.read_u16()?;
+                }
+
+                FifoMode::Drawing(state)
+            }
+            _ => return Err(SaveStateError::InvalidData),
+        };
+
+        Ok(())
+    }
+}
+
+impl From<PixelFetcherState> for u8 {
+    fn from(item: PixelFetcherState) -> u8 {
+        match item {
+            PixelFetcherState::GetTile => 0,
+            PixelFetcherState::GetTileLow => 1,
+            PixelFetcherState::GetTileHigh => 2,
+            PixelFetcherState::Push => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for PixelFetcherState {
+    type Error = SaveStateError;
+
+    fn try_from(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(PixelFetcherState::GetTile),
+            1 => Ok(PixelFetcherState::GetTileLow),
+            2 => Ok(PixelFetcherState::GetTileHigh),
+            3 => Ok(PixelFetcherState::Push),
+            _ => Err(SaveStateError::InvalidData),
+        }
+    }
+}
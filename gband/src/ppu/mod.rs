@@ -5,18 +5,22 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 mod cgb_palette;
+mod compatibility_palette;
 mod fifo_mode;
 mod lcd_control;
 mod lcd_status;
 mod palette_table;
 mod pixel_fifo;
 
+pub(crate) use cgb_palette::rgb555_to_rgb888;
 use cgb_palette::CgbPalette;
+pub use compatibility_palette::CompatibilityPalette;
 pub(crate) use fifo_mode::FifoMode;
 use lcd_control::LcdControl;
 use lcd_status::LcdStatus;
 
 use crate::bus::PpuBus;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 use crate::InterruptReg;
 
 use self::{
@@ -31,6 +35,8 @@ pub type Frame = Box<[u8; FRAME_WIDTH * FRAME_HEIGHT * 4]>;
 
 pub struct Ppu {
     cgb_mode: bool,
+    // DMG game on a CGB, the DMG palettes are mapped through the CGB palettes
+    compatibility_mode: bool,
 
     x: u8,
     y: u8,
@@ -55,8 +61,12 @@ pub struct Ppu {
     dmg_bg_palette: u8,
     dmg_obj_palette: [u8; 2],
 
-    dmg_colorized_bg_palette: [[u8; 3]; 4],
-    dmg_colorized_obj_palette: [[[u8; 3]; 4]; 2],
+    // Colors of the shades on models without a CGB
+    dmg_bg_shades: [[u8; 3]; 4],
+    dmg_obj_shades: [[[u8; 3]; 4]; 2],
+
+    // OPRI, 0 for CGB (OAM index) priority and 1 for DMG (X coordinate) priority
+    object_priority_mode: u8,
 
     lcd_control_reg: LcdControl,
     lcd_status_reg: LcdStatus,
@@ -68,12 +78,18 @@ pub struct Ppu {
     paused_cycles: u32,
     fifo_mode: FifoMode,
     frame: Frame,
+
+    // Shades of the frame being drawn and of the last one, for the SGB which colors the screen itself.
+    // Empty on other models.
+    shades: Vec<u8>,
+    ready_shades: Vec<u8>,
 }
 
 impl Default for Ppu {
     fn default() -> Self {
         Self {
             cgb_mode: false,
+            compatibility_mode: false,
 
             x: 0,
             y: 0,
@@ -108,8 +124,10 @@ impl Default for Ppu {
             dmg_bg_palette: 0,
             dmg_obj_palette: [0; 2],
 
-            dmg_colorized_bg_palette: Default::default(),
-            dmg_colorized_obj_palette: Default::default(),
+            dmg_bg_shades: Default::default(),
+            dmg_obj_shades: Default::default(),
+
+            object_priority_mode: 1,
 
             background_pixel_pipeline: Default::default(),
             sprite_pixel_pipeline: Default::default(),
@@ -118,6 +136,9 @@ impl Default for Ppu {
             paused_cycles: 0,
             fifo_mode: Default::default(),
             frame: allocate_new_frame(),
+
+            shades: Vec::new(),
+            ready_shades: Vec::new(),
         }
     }
 }
@@ -126,18 +147,75 @@ impl Ppu {
     pub fn new(cgb_mode: bool) -> Self {
         Self {
             cgb_mode,
+            object_priority_mode: if cgb_mode { 0 } else { 1 },
             ..Default::default()
         }
     }
 
+    /// Switch a CGB to DMG compatibility mode, which is normally done by the boot ROM
+    pub fn set_compatibility_mode(&mut self) {
+        self.cgb_mode = false;
+        self.compatibility_mode = true;
+    }
+
+    /// Keep the shade of every pixel, so the SGB can color them
+    pub fn set_sgb_mode(&mut self) {
+        self.shades = vec![0; FRAME_WIDTH * FRAME_HEIGHT];
+        self.ready_shades = vec![0; FRAME_WIDTH * FRAME_HEIGHT];
+    }
+
+    /// Shades of the last frame returned by `ready_frame`, from 0 (lightest) to 3
+    pub fn sgb_shades(&self) -> &[u8] {
+        &self.ready_shades
+    }
+
+    /// The boot ROM initializes the palettes itself, so they start zeroed instead of white
+    pub fn clear_palettes(&mut self) {
+        self.cgb_bg_palette.data = [0u8; 0x40];
+        self.cgb_obj_palette.data = [0u8; 0x40];
+    }
+
+    /// Load the palettes the CGB boot ROM picks from the title of a DMG game
     pub fn set_dmg_colorized_palette(&mut self, title: &[u8; 16]) {
         let hash: Wrapping<u8> = title.iter().map(|x| Wrapping(*x)).sum();
 
         let palettes = palette_table::palette_fill_from_hash(hash.0, title[3]);
 
-        self.dmg_colorized_bg_palette = palettes[0];
-        self.dmg_colorized_obj_palette[0] = palettes[1];
-        self.dmg_colorized_obj_palette[1] = palettes[2];
+        self.load_compatibility_palettes(palettes);
+    }
+
+    /// Replace the palettes of a DMG game with one of the palettes selectable at boot.
+    /// Does nothing outside of compatibility mode.
+    pub fn set_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        if self.compatibility_mode {
+            self.load_compatibility_palettes(palette.colors());
+        }
+    }
+
+    pub fn is_compatibility_mode(&self) -> bool {
+        self.compatibility_mode
+    }
+
+    /// In compatibility mode, BGP uses the CGB background palette 0, and OBP0 and OBP1 use the CGB object palettes 0 and 1
+    fn load_compatibility_palettes(&mut self, palettes: [[[u8; 3]; 4]; 3]) {
+        for (color, rgb) in palettes[0].iter().enumerate() {
+            self.cgb_bg_palette.set_rgb(0, color, *rgb);
+        }
+
+        for (palette, colors) in palettes[1..].iter().enumerate() {
+            for (color, rgb) in colors.iter().enumerate() {
+                self.cgb_obj_palette.set_rgb(palette, color, *rgb);
+            }
+        }
+    }
+
+    /// Models without a CGB display the shades as-is
+    pub fn set_dmg_grayscale_palette(&mut self) {
+        let palettes = palette_table::GRAYSCALE_PALETTE;
+
+        self.dmg_bg_shades = palettes[0];
+        self.dmg_obj_shades[0] = palettes[1];
+        self.dmg_obj_shades[1] = palettes[2];
     }
 
     pub fn clock(&mut self, bus: &mut PpuBus) {
@@ -224,6 +302,29 @@ impl Ppu {
         self.render(bus);
     }
 
+    /// Number of T-cycles before the PPU has anything else to do than counting cycles
+    pub fn idle_cycles(&self) -> u32 {
+        if !self.lcd_control_reg.contains(LcdControl::LCD_PPU_ENABLE) {
+            // The next event is pushing a blank frame
+            return 70223u32.saturating_sub(self.paused_cycles);
+        }
+
+        match self.fifo_mode {
+            // Nothing happens until the end of the line
+            FifoMode::HBlank | FifoMode::VBlank => 455u32.saturating_sub(self.cycle as u32),
+            _ => 0,
+        }
+    }
+
+    /// Skip cycles returned by `idle_cycles`
+    pub fn skip(&mut self, cycles: u32) {
+        if self.lcd_control_reg.contains(LcdControl::LCD_PPU_ENABLE) {
+            self.cycle += cycles as u16;
+        } else {
+            self.paused_cycles += cycles;
+        }
+    }
+
     pub fn ready_frame(&mut self) -> Option<Frame> {
         let is_ready = if self.lcd_control_reg.contains(LcdControl::LCD_PPU_ENABLE) {
             self.y == 0 && self.cycle == 0
@@ -237,6 +338,10 @@ impl Ppu {
             // Replace current frame with the newly allocated one
             let frame = core::mem::replace(&mut self.frame, new_frame);
 
+            // The new frame starts out white, and so do its shades
+            core::mem::swap(&mut self.shades, &mut self.ready_shades);
+            self.shades.fill(0);
+
             Some(frame)
         } else {
             None
@@ -352,14 +457,12 @@ impl Ppu {
             0xFF48 | 0xFF49 => self.dmg_obj_palette[(addr & 1) as usize] = data,
             0xFF4A => self.window_y = data,
             0xFF4B => self.window_x = data,
-            0xFF4C => {
-                // rKEY0 is blocked after boot
-            }
             0xFF4F => self.vram_bank_register = data & 1 > 0,
             0xFF68 => self.cgb_bg_palette.write_spec(data),
             0xFF69 => self.cgb_bg_palette.write_data(data, self.fifo_mode),
             0xFF6A => self.cgb_obj_palette.write_spec(data),
             0xFF6B => self.cgb_obj_palette.write_data(data, self.fifo_mode),
+            0xFF6C => self.object_priority_mode = data & 1,
             _ => {
                 // Address not recognised, do nothing
             }
@@ -378,10 +481,6 @@ impl Ppu {
             0xFF48 | 0xFF49 => self.dmg_obj_palette[(addr & 1) as usize],
             0xFF4A => self.window_y,
             0xFF4B => self.window_x,
-            0xFF4C => {
-                // rKEY0 is blocked after boot
-                0xFF
-            }
             0xFF4F => {
                 let bank_bit = if self.vram_bank_register { 1 } else { 0 };
                 0xFE | bank_bit
@@ -390,6 +489,7 @@ impl Ppu {
             0xFF69 => self.cgb_bg_palette.read_data(self.fifo_mode),
             0xFF6A => self.cgb_obj_palette.read_spec(),
             0xFF6B => self.cgb_obj_palette.read_data(self.fifo_mode),
+            0xFF6C => 0xFE | self.object_priority_mode,
             _ => {
                 // Address not recognised, do nothing
                 0
@@ -590,7 +690,8 @@ impl Ppu {
                                 *b |= (state.sprite_idx as u16) << 12;
                             }
 
-                            self.sprite_pixel_pipeline.load(state.buffer, self.cgb_mode);
+                            self.sprite_pixel_pipeline
+                                .load(state.buffer, self.object_priority_mode == 0);
 
                             if self.x == 0 {
                                 self.sprite_pixel_pipeline
@@ -671,17 +772,22 @@ impl Ppu {
                         {
                             // Pixel is transparent or under the background. Rendering background instead
                             // Index the pixel in the palette
-                            if self
+                            let index = if self
                                 .lcd_control_reg
                                 .contains(LcdControl::BACKGROUND_WINDOW_ENABLE_PRIORITY)
                             {
-                                let index = (self.dmg_bg_palette
-                                    >> (((background_pixel >> 8) as u8 & 3) << 1))
-                                    & 0x3;
-                                self.dmg_colorized_bg_palette[index as usize]
+                                (self.dmg_bg_palette >> (((background_pixel >> 8) as u8 & 3) << 1))
+                                    & 0x3
                             } else {
                                 // Renders white if background rendering is disabled
-                                [0xFF, 0xFF, 0xFF]
+                                0
+                            };
+                            self.record_shade(index);
+
+                            if self.compatibility_mode {
+                                self.cgb_bg_palette.get_rgb(0, index as usize)
+                            } else {
+                                self.dmg_bg_shades[index as usize]
                             }
                         } else {
                             // Rendering the sprite pixel
@@ -689,8 +795,13 @@ impl Ppu {
                             let index = (self.dmg_obj_palette[sprite_palette]
                                 >> (((sprite_pixel >> 8) as u8 & 3) << 1))
                                 & 0x3;
+                            self.record_shade(index);
 
-                            self.dmg_colorized_obj_palette[sprite_palette][index as usize]
+                            if self.compatibility_mode {
+                                self.cgb_obj_palette.get_rgb(sprite_palette, index as usize)
+                            } else {
+                                self.dmg_obj_shades[sprite_palette][index as usize]
+                            }
                         }
                     };
 
@@ -738,6 +849,13 @@ impl Ppu {
         self.fifo_mode = fifo_mode;
     }
 
+    fn record_shade(&mut self, shade: u8) {
+        let index = (self.y as usize) * FRAME_WIDTH + (self.x as usize);
+        if let Some(pixel) = self.shades.get_mut(index) {
+            *pixel = shade;
+        }
+    }
+
     fn read_bg_win_tile(&self, bank: u8, id: u8, offset: u8) -> u8 {
         // See: https://gbdev.io/pandocs/Tile_Data.html
         if self
@@ -892,6 +1010,120 @@ impl Ppu {
     }
 }
 
+impl SaveState for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.cgb_mode);
+        writer.write_bool(self.compatibility_mode);
+
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u8(self.window_y_counter);
+        writer.write_bool(self.window_y_flag);
+        writer.write_u8(self.y_compare);
+        writer.write_u8(self.window_x);
+        writer.write_u8(self.window_y);
+        writer.write_u8(self.scroll_x);
+        writer.write_u8(self.scroll_y);
+
+        writer.write_bytes(&self.vram);
+        writer.write_bool(self.vram_bank_register);
+        writer.write_bytes(&self.oam);
+        writer.write_bytes(&self.secondary_oam);
+
+        self.cgb_bg_palette.save_state(writer);
+        self.cgb_obj_palette.save_state(writer);
+
+        writer.write_u8(self.dmg_bg_palette);
+        writer.write_bytes(&self.dmg_obj_palette);
+        for color in self.dmg_bg_shades {
+            writer.write_bytes(&color);
+        }
+        for color in self.dmg_obj_shades.iter().flatten() {
+            writer.write_bytes(color);
+        }
+        writer.write_u8(self.object_priority_mode);
+
+        writer.write_u8(self.lcd_control_reg.bits());
+        writer.write_u8(self.lcd_status_reg.bits());
+
+        self.background_pixel_pipeline.save_state(writer);
+        self.sprite_pixel_pipeline.save_state(writer);
+
+        writer.write_u16(self.cycle);
+        writer.write_u32(self.paused_cycles);
+        self.fifo_mode.save_state(writer);
+
+        // The frame currently being drawn, so a state taken mid-frame renders the same
+        writer.write_bytes(self.frame.as_slice());
+        writer.write_bytes(&self.shades);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cgb_mode = reader.read_bool()?;
+        // Older versions rendered DMG games with the shades directly
+        self.compatibility_mode = if reader.version() >= 4 {
+            reader.read_bool()?
+        } else {
+            false
+        };
+
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.window_y_counter = reader.read_u8()?;
+        self.window_y_flag = reader.read_bool()?;
+        self.y_compare = reader.read_u8()?;
+        self.window_x = reader.read_u8()?;
+        self.window_y = reader.read_u8()?;
+        self.scroll_x = reader.read_u8()?;
+        self.scroll_y = reader.read_u8()?;
+
+        reader.read_bytes(&mut self.vram)?;
+        self.vram_bank_register = reader.read_bool()?;
+        reader.read_bytes(&mut self.oam)?;
+        reader.read_bytes(&mut self.secondary_oam)?;
+
+        self.cgb_bg_palette.load_state(reader)?;
+        self.cgb_obj_palette.load_state(reader)?;
+
+        self.dmg_bg_palette = reader.read_u8()?;
+        reader.read_bytes(&mut self.dmg_obj_palette)?;
+        for color in &mut self.dmg_bg_shades {
+            reader.read_bytes(color)?;
+        }
+        for color in self.dmg_obj_shades.iter_mut().flatten() {
+            reader.read_bytes(color)?;
+        }
+        self.object_priority_mode = if reader.version() >= 4 {
+            reader.read_u8()? & 1
+        } else if self.cgb_mode {
+            0
+        } else {
+            1
+        };
+
+        self.lcd_control_reg = LcdControl::from_bits_truncate(reader.read_u8()?);
+        self.lcd_status_reg = LcdStatus::from_bits_truncate(reader.read_u8()?);
+
+        self.background_pixel_pipeline.load_state(reader)?;
+        self.sprite_pixel_pipeline.load_state(reader)?;
+
+        self.cycle = reader.read_u16()?;
+        self.paused_cycles = reader.read_u32()?;
+        self.fifo_mode.load_state(reader)?;
+
+        reader.read_bytes(self.frame.as_mut_slice())?;
+
+        // Versions before 9 didn't support the SGB
+        if reader.version() >= 9 {
+            reader.read_bytes(&mut self.shades)?;
+        } else {
+            self.shades.fill(0);
+        }
+
+        Ok(())
+    }
+}
+
 fn allocate_new_frame() -> Frame {
     //   Hackish way to create fixed size boxed array.
     // I don't know of any way to do it without
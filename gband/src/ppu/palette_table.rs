@@ -4230,7 +4230,29 @@ const PALETTE_HASH_ENTRIES: [PaletteHashEntry; 94] = [
     },
 ];
 
-fn palette_fill_from_table_entry(table: usize, entry: usize) -> [[[u8; 3]; 4]; 3] {
+/// Used on models without colorization
+pub const GRAYSCALE_PALETTE: [[[u8; 3]; 4]; 3] = [
+    [
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+        [0x00, 0x00, 0x00],
+    ],
+    [
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+        [0x00, 0x00, 0x00],
+    ],
+    [
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+        [0x00, 0x00, 0x00],
+    ],
+];
+
+pub fn palette_fill_from_table_entry(table: usize, entry: usize) -> [[[u8; 3]; 4]; 3] {
     if table > PALETTE_TABLES.len() - 1 || entry > PALETTE_TABLES[table].len() - 1 {
         DUMMY_PALETTE
     } else {
@@ -1,3 +1,5 @@
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Default, Clone)]
 pub struct PixelFifo {
     pub fifo: [u16; 8],
@@ -46,3 +48,20 @@ impl PixelFifo {
         self.n_pixels = 8;
     }
 }
+
+impl SaveState for PixelFifo {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for pixel in self.fifo {
+            writer.write_u16(pixel);
+        }
+        writer.write_u8(self.n_pixels);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for pixel in &mut self.fifo {
+            *pixel = reader.read_u16()?;
+        }
+        self.n_pixels = reader.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use num_enum::TryFromPrimitive;
+
+use crate::SerialTransport;
+
+/// Every packet starts with these bytes
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+/// Sent back after the checksum, it identifies the device as a printer
+const DEVICE_ID: u8 = 0x81;
+
+/// Width of the paper, in pixels
+pub const PRINT_WIDTH: usize = 160;
+
+/// The printer holds up to 9 DATA packets of 2 rows of 20 tiles
+const TILE_LEN: usize = 16;
+const TILE_ROW_LEN: usize = TILE_LEN * PRINT_WIDTH / 8;
+const MAX_IMAGE_LEN: usize = TILE_ROW_LEN * 2 * 9;
+
+/// Number of packets answered as busy after a print, games wait for it to be done
+const BUSY_PACKETS: u8 = 4;
+
+/// Gray level of the shades, from white to black
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+bitflags! {
+    struct PrinterStatus: u8 {
+        const CHECKSUM_ERROR = 0x01;
+        const BUSY = 0x02;
+        const IMAGE_FULL = 0x04;
+        const UNPROCESSED_DATA = 0x08;
+        const PACKET_ERROR = 0x10;
+        const PAPER_JAM = 0x20;
+        const OTHER_ERROR = 0x40;
+        const LOW_BATTERY = 0x80;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+enum Command {
+    Init = 0x01,
+    Print = 0x02,
+    Data = 0x04,
+    Break = 0x08,
+    Status = 0x0F,
+}
+
+/// Position within a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    DeviceId,
+    Status,
+}
+
+/// Strips printed until the paper was fed, as 8-bit grayscale
+pub struct PrintedPage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// Game Boy Printer connected to the link port.
+/// Strips are printed on the same page until a print feeds the paper after them,
+/// the page is then passed to the callback.
+pub struct PrinterSerialTransport {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+
+    /// Byte sent back to the Game Boy for the byte it just sent
+    reply: Option<u8>,
+    status: PrinterStatus,
+    busy_packets: u8,
+
+    /// Tiles received since the last print
+    image: Vec<u8>,
+    /// Lines printed since the paper was last fed
+    page: Vec<u8>,
+    on_print: Box<dyn FnMut(PrintedPage) + Send + Sync>,
+}
+
+impl PrinterSerialTransport {
+    pub fn new(on_print: Box<dyn FnMut(PrintedPage) + Send + Sync>) -> Self {
+        Self {
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+
+            reply: None,
+            status: PrinterStatus::empty(),
+            busy_packets: 0,
+
+            image: Vec::new(),
+            page: Vec::new(),
+            on_print,
+        }
+    }
+
+    /// Handle a byte of a packet and return the byte sent back
+    fn receive(&mut self, data: u8) -> u8 {
+        // Everything but the magic bytes and the replies is part of the checksum
+        if !matches!(
+            self.state,
+            PacketState::Magic(_)
+                | PacketState::Checksum(_)
+                | PacketState::DeviceId
+                | PacketState::Status
+        ) {
+            self.checksum = self.checksum.wrapping_add(data as u16);
+        }
+
+        match self.state {
+            PacketState::Magic(i) => {
+                self.state = if data != MAGIC[i] {
+                    PacketState::Magic(0)
+                } else if i + 1 == MAGIC.len() {
+                    PacketState::Command
+                } else {
+                    PacketState::Magic(i + 1)
+                };
+                self.checksum = 0;
+            }
+            PacketState::Command => {
+                self.command = data;
+                self.state = PacketState::Compression;
+            }
+            PacketState::Compression => {
+                self.compressed = data & 0x01 != 0;
+                self.state = PacketState::Length(0);
+            }
+            PacketState::Length(0) => {
+                self.length = data as u16;
+                self.state = PacketState::Length(1);
+            }
+            PacketState::Length(_) => {
+                self.length |= (data as u16) << 8;
+                self.data.clear();
+                self.state = if self.length == 0 {
+                    PacketState::Checksum(0)
+                } else {
+                    PacketState::Data
+                };
+            }
+            PacketState::Data => {
+                self.data.push(data);
+                if self.data.len() == self.length as usize {
+                    self.state = PacketState::Checksum(0);
+                }
+            }
+            PacketState::Checksum(0) => {
+                self.received_checksum = data as u16;
+                self.state = PacketState::Checksum(1);
+            }
+            PacketState::Checksum(_) => {
+                self.received_checksum |= (data as u16) << 8;
+                self.state = PacketState::DeviceId;
+            }
+            PacketState::DeviceId => {
+                if self.received_checksum == self.checksum {
+                    self.status.remove(PrinterStatus::CHECKSUM_ERROR);
+                    self.execute();
+                } else {
+                    log::warn!("Invalid checksum in printer packet");
+                    self.status.insert(PrinterStatus::CHECKSUM_ERROR);
+                }
+
+                self.state = PacketState::Status;
+                return DEVICE_ID;
+            }
+            PacketState::Status => {
+                self.state = PacketState::Magic(0);
+
+                let mut status = self.status;
+                if self.busy_packets > 0 {
+                    self.busy_packets -= 1;
+                    status.insert(PrinterStatus::BUSY);
+                }
+                return status.bits();
+            }
+        }
+
+        0x00
+    }
+
+    fn execute(&mut self) {
+        let command = match Command::try_from(self.command) {
+            Ok(command) => command,
+            Err(_) => {
+                log::warn!("Unknown printer command: {:02x}", self.command);
+                self.status.insert(PrinterStatus::PACKET_ERROR);
+                return;
+            }
+        };
+
+        match command {
+            Command::Init => {
+                self.image.clear();
+                self.status = PrinterStatus::empty();
+                self.busy_packets = 0;
+            }
+            Command::Data => {
+                // An empty packet marks the end of the data
+                if !self.data.is_empty() {
+                    let data = core::mem::take(&mut self.data);
+                    if self.compressed {
+                        self.decompress(&data);
+                    } else {
+                        self.image.extend_from_slice(&data);
+                    }
+                    self.data = data;
+
+                    self.image.truncate(MAX_IMAGE_LEN);
+                    self.status.insert(PrinterStatus::UNPROCESSED_DATA);
+                    self.status
+                        .set(PrinterStatus::IMAGE_FULL, self.image.len() == MAX_IMAGE_LEN);
+                }
+            }
+            Command::Print => {
+                if let [sheets, margins, palette, _exposure] = self.data[..] {
+                    self.print(sheets, margins, palette);
+                } else {
+                    self.status.insert(PrinterStatus::PACKET_ERROR);
+                }
+            }
+            Command::Break => {
+                self.image.clear();
+                self.status
+                    .remove(PrinterStatus::UNPROCESSED_DATA | PrinterStatus::IMAGE_FULL);
+            }
+            Command::Status => {}
+        }
+    }
+
+    /// Run-length encoding: the high bit of a control byte repeats the next byte,
+    /// otherwise the next bytes are copied as-is
+    fn decompress(&mut self, data: &[u8]) {
+        let mut data = data.iter();
+
+        while let Some(&control) = data.next() {
+            if control & 0x80 != 0 {
+                let count = (control & 0x7F) as usize + 2;
+                if let Some(&value) = data.next() {
+                    self.image.extend(core::iter::repeat_n(value, count));
+                }
+            } else {
+                let count = control as usize + 1;
+                self.image.extend(data.by_ref().take(count));
+            }
+        }
+    }
+
+    fn print(&mut self, sheets: u8, margins: u8, palette: u8) {
+        // A margin before the image starts a new page
+        if margins >> 4 != 0 {
+            self.feed_paper();
+        }
+
+        // Games that don't bother setting a palette expect the usual one
+        let palette = if palette == 0 { 0xE4 } else { palette };
+
+        for _ in 0..sheets {
+            for tile_row in self.image.chunks_exact(TILE_ROW_LEN) {
+                for row in 0..8 {
+                    for x in 0..PRINT_WIDTH {
+                        let tile = &tile_row[(x / 8) * TILE_LEN..];
+                        let bit = 7 - (x % 8);
+                        let index = ((tile[row * 2] >> bit) & 0x01)
+                            | (((tile[row * 2 + 1] >> bit) & 0x01) << 1);
+                        let shade = (palette >> (index * 2)) & 0x03;
+                        self.page.push(SHADES[shade as usize]);
+                    }
+                }
+            }
+        }
+
+        self.image.clear();
+        self.status
+            .remove(PrinterStatus::UNPROCESSED_DATA | PrinterStatus::IMAGE_FULL);
+        self.busy_packets = BUSY_PACKETS;
+
+        if margins & 0x0F != 0 {
+            self.feed_paper();
+        }
+    }
+
+    fn feed_paper(&mut self) {
+        if !self.page.is_empty() {
+            let pixels = core::mem::take(&mut self.page);
+            (self.on_print)(PrintedPage {
+                width: PRINT_WIDTH,
+                height: pixels.len() / PRINT_WIDTH,
+                pixels,
+            });
+        }
+    }
+}
+
+impl SerialTransport for PrinterSerialTransport {
+    fn connect(&mut self) -> bool {
+        true
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.state = PacketState::Magic(0);
+        self.reply = None;
+    }
+
+    fn send(&mut self, data: u8) {
+        let reply = self.receive(data);
+        self.reply = Some(reply);
+    }
+
+    fn recv(&mut self) -> Option<u8> {
+        // The printer is never the master, it only answers
+        self.reply.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::sync::Arc;
+    use alloc::vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    fn send_packet(
+        printer: &mut PrinterSerialTransport,
+        command: u8,
+        compressed: bool,
+        data: &[u8],
+    ) -> u8 {
+        let mut packet = vec![command, compressed as u8];
+        packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(data);
+
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+
+        let mut replies = Vec::new();
+        for byte in MAGIC.iter().chain(packet.iter()).chain([0x00, 0x00].iter()) {
+            printer.send(*byte);
+            replies.push(printer.recv().unwrap());
+        }
+
+        assert_eq!(replies[replies.len() - 2], DEVICE_ID);
+        replies[replies.len() - 1]
+    }
+
+    #[test]
+    fn test_print() {
+        let printed_lines = Arc::new(AtomicUsize::new(0));
+        let lines = printed_lines.clone();
+        let mut printer = PrinterSerialTransport::new(Box::new(move |page| {
+            assert_eq!(page.width, PRINT_WIDTH);
+            lines.fetch_add(page.height, Ordering::Relaxed);
+        }));
+
+        assert_eq!(send_packet(&mut printer, 0x01, false, &[]), 0x00);
+
+        // Two rows of tiles where every pixel uses color 1, then 2
+        let mut data = [0xFFu8, 0x00].repeat(TILE_ROW_LEN / 2);
+        data.extend([0x00, 0xFF].repeat(TILE_ROW_LEN / 2));
+        assert_eq!(send_packet(&mut printer, 0x04, false, &data), 0x08);
+        assert_eq!(send_packet(&mut printer, 0x04, false, &[]), 0x08);
+
+        // Print without feeding the paper after, with color 1 as black and color 2 as white
+        let status = send_packet(&mut printer, 0x02, false, &[1, 0x10, 0x0C, 0x40]);
+        assert_eq!(status, 0x02);
+        assert_eq!(printer.page.len(), PRINT_WIDTH * 16);
+        assert_eq!(printer.page[0], 0x00);
+        assert_eq!(printer.page[PRINT_WIDTH * 8], 0xFF);
+        assert_eq!(printed_lines.load(Ordering::Relaxed), 0);
+
+        // The printer stays busy for a few packets
+        while send_packet(&mut printer, 0x0F, false, &[]) & 0x02 != 0 {}
+
+        // A compressed row of color 3, then feeding the paper finishes the page
+        let data = [0xFF, 0xFF, 0xFF, 0xFF, 0xBC, 0xFF];
+        send_packet(&mut printer, 0x04, true, &data);
+        send_packet(&mut printer, 0x02, false, &[1, 0x03, 0xE4, 0x40]);
+        assert_eq!(printed_lines.load(Ordering::Relaxed), 24);
+        assert!(printer.page.is_empty());
+
+        // Corrupted packets are reported
+        for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00] {
+            printer.send(byte);
+            printer.recv();
+        }
+        printer.send(0x00);
+        assert_eq!(printer.recv().unwrap() & 0x01, 0x01);
+    }
+}
@@ -0,0 +1,190 @@
+use alloc::vec::Vec;
+
+/// Magic bytes at the start of every save state
+const MAGIC: [u8; 4] = *b"GBSS";
+
+/// Current version of the save state format.
+/// Bump this whenever the layout of any component changes.
+pub const SAVE_STATE_VERSION: u16 = 10;
+
+/// Oldest version of the format that can still be loaded.
+pub const SAVE_STATE_MIN_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SaveStateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    SgbMismatch,
+    UnexpectedEof,
+    InvalidData,
+}
+
+impl core::fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:?}", &self)
+    }
+}
+
+/// Implemented by every piece of hardware that needs to survive a save state.
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn write_header(&mut self, rom_id: &[u8]) {
+        self.write_bytes(&MAGIC);
+        self.write_u16(SAVE_STATE_VERSION);
+        self.write_u8(rom_id.len() as u8);
+        self.write_bytes(rom_id);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    /// Writes a variable length buffer, prefixed by its length
+    pub fn write_slice(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.write_bytes(value);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header of the save state and returns a reader positioned after it.
+    pub fn new(data: &'a [u8], rom_id: &[u8]) -> Result<Self, SaveStateError> {
+        let mut reader = Self {
+            data,
+            position: 0,
+            version: 0,
+        };
+
+        let mut magic = [0u8; 4];
+        reader.read_bytes(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SaveStateError::InvalidMagic);
+        }
+
+        let version = reader.read_u16()?;
+        if !(SAVE_STATE_MIN_VERSION..=SAVE_STATE_VERSION).contains(&version) {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        reader.version = version;
+
+        let id_len = reader.read_u8()? as usize;
+        if reader.take(id_len)? != rom_id {
+            return Err(SaveStateError::RomMismatch);
+        }
+
+        Ok(reader)
+    }
+
+    /// Version of the state being read, used to migrate older layouts
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::InvalidData),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut buf = [0u8; 2];
+        self.read_bytes(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut buf = [0u8; 8];
+        self.read_bytes(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), SaveStateError> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    /// Reads a buffer written with `StateWriter::write_slice`.
+    /// The length must match the size of the destination.
+    pub fn read_slice(&mut self, buf: &mut [u8]) -> Result<(), SaveStateError> {
+        if self.read_u32()? as usize != buf.len() {
+            return Err(SaveStateError::InvalidData);
+        }
+
+        self.read_bytes(buf)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .ok_or(SaveStateError::UnexpectedEof)?;
+
+        let data = self
+            .data
+            .get(self.position..end)
+            .ok_or(SaveStateError::UnexpectedEof)?;
+
+        self.position = end;
+        Ok(data)
+    }
+}
@@ -1,6 +1,7 @@
 use alloc::boxed::Box;
 use bitflags::bitflags;
 
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 use crate::{NullSerialTransport, SerialTransport};
 
 const N_BIT_CYCLES: u8 = 8;
@@ -56,13 +57,7 @@ impl SerialPort {
     pub fn clock(&mut self) -> bool {
         self.freq_downscale_cycle += 1;
 
-        let speed = if self.control.contains(ControlRegister::FAST) {
-            CPU_CYCLES_FAST
-        } else {
-            CPU_CYCLES
-        };
-
-        if self.freq_downscale_cycle >= speed {
+        if self.freq_downscale_cycle >= self.speed() {
             self.freq_downscale_cycle = 0;
 
             if self.control.contains(ControlRegister::START) {
@@ -75,6 +70,40 @@ impl SerialPort {
         }
     }
 
+    /// Number of CPU cycles before the serial port has anything to do
+    pub fn idle_cycles(&self) -> u32 {
+        if self.control.contains(ControlRegister::START) {
+            0
+        } else {
+            u32::MAX
+        }
+    }
+
+    /// Skip cycles while no transfer is in progress, only keeping the clock divider in sync
+    pub fn skip(&mut self, cycles: u32) {
+        if cycles == 0 {
+            return;
+        }
+
+        let speed = self.speed() as u32;
+        let position = self.freq_downscale_cycle as u32;
+
+        // The divider might be past the end after switching to the fast clock, it then wraps on the next cycle
+        self.freq_downscale_cycle = if position >= speed {
+            ((cycles - 1) % speed) as u8
+        } else {
+            ((position + cycles) % speed) as u8
+        };
+    }
+
+    fn speed(&self) -> u8 {
+        if self.control.contains(ControlRegister::FAST) {
+            CPU_CYCLES_FAST
+        } else {
+            CPU_CYCLES
+        }
+    }
+
     pub fn set_serial(&mut self, serial: alloc::boxed::Box<dyn SerialTransport>) {
         self.serial_transport = serial
     }
@@ -151,3 +180,25 @@ impl SerialPort {
         self.control.bits()
     }
 }
+
+impl SaveState for SerialPort {
+    // Note: the transport itself is not part of the state, the link stays connected as-is
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.buffer);
+        writer.write_u8(self.control.bits());
+        writer.write_u8(self.freq_downscale_cycle);
+        writer.write_u8(self.bit_cycle);
+        writer.write_u8(self.receive_latch);
+        writer.write_bool(self.skip_send);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.buffer = reader.read_u8()?;
+        self.control = ControlRegister::from_bits_truncate(reader.read_u8()?);
+        self.freq_downscale_cycle = reader.read_u8()?;
+        self.bit_cycle = reader.read_u8()?;
+        self.receive_latch = reader.read_u8()?;
+        self.skip_send = reader.read_bool()?;
+        Ok(())
+    }
+}
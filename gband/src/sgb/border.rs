@@ -0,0 +1,119 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{SGB_FRAME_WIDTH, VRAM_TRANSFER_LEN};
+use crate::ppu::rgb555_to_rgb888;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Bytes in a tile, in the 4 bits per pixel format of the SNES
+const TILE_LEN: usize = 32;
+
+/// The map is 32x32 tiles, but only 28 rows are visible
+const MAP_WIDTH: usize = 32;
+const VISIBLE_ROWS: usize = 28;
+
+/// Picture drawn around the screen by the SNES, sent with CHR_TRN and PCT_TRN
+pub struct Border {
+    /// 256 tiles, sent as two halves by CHR_TRN
+    tiles: Vec<u8>,
+    /// Tile number, palette and flips of each tile of the map
+    map: Vec<u16>,
+    /// Palettes 4 to 7 of the SNES, in RGB555. Color 0 is transparent.
+    palettes: [[u16; 16]; 4],
+}
+
+impl Default for Border {
+    fn default() -> Self {
+        Self {
+            tiles: vec![0; TILE_LEN * 256],
+            map: vec![0; MAP_WIDTH * MAP_WIDTH],
+            palettes: [[0; 16]; 4],
+        }
+    }
+}
+
+impl Border {
+    /// Load the tiles 0x00-0x7F, or 0x80-0xFF when `upper` is set
+    pub fn load_tiles(&mut self, upper: bool, data: &[u8]) {
+        let start = if upper { VRAM_TRANSFER_LEN } else { 0 };
+        self.tiles[start..start + VRAM_TRANSFER_LEN].copy_from_slice(data);
+    }
+
+    /// Load the map, followed by the palettes
+    pub fn load_map(&mut self, data: &[u8]) {
+        let (map, palettes) = data.split_at(MAP_WIDTH * MAP_WIDTH * 2);
+
+        for (entry, bytes) in self.map.iter_mut().zip(map.chunks_exact(2)) {
+            *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        for (color, bytes) in self
+            .palettes
+            .iter_mut()
+            .flatten()
+            .zip(palettes.chunks_exact(2))
+        {
+            *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+
+    /// Draw the opaque pixels of the border over an RGBA frame
+    pub fn draw(&self, output: &mut [u8]) {
+        for (index, &entry) in self.map[..MAP_WIDTH * VISIBLE_ROWS].iter().enumerate() {
+            let tile = &self.tiles[(entry as usize & 0xFF) * TILE_LEN..][..TILE_LEN];
+            // Borders can only use the palettes 4 to 7
+            let palette = &self.palettes[(entry as usize >> 10) & 0x03];
+            let flip_x = entry & 0x4000 != 0;
+            let flip_y = entry & 0x8000 != 0;
+
+            for row in 0..8 {
+                let tile_row = if flip_y { 7 - row } else { row };
+                // Bitplanes 0 and 1 are interleaved in the first half, 2 and 3 in the second
+                let planes = [
+                    tile[tile_row * 2],
+                    tile[tile_row * 2 + 1],
+                    tile[16 + tile_row * 2],
+                    tile[16 + tile_row * 2 + 1],
+                ];
+
+                for column in 0..8 {
+                    let bit = if flip_x { column } else { 7 - column };
+                    let color = planes.iter().enumerate().fold(0, |color, (plane, bits)| {
+                        color | (((bits >> bit) & 1) << plane)
+                    });
+
+                    if color != 0 {
+                        let x = (index % MAP_WIDTH) * 8 + column;
+                        let y = (index / MAP_WIDTH) * 8 + row;
+                        let base = (y * SGB_FRAME_WIDTH + x) * 4;
+                        output[base..base + 3]
+                            .copy_from_slice(&rgb555_to_rgb888(palette[color as usize]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SaveState for Border {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.tiles);
+        for entry in &self.map {
+            writer.write_u16(*entry);
+        }
+        for color in self.palettes.iter().flatten() {
+            writer.write_u16(*color);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.tiles)?;
+        for entry in &mut self.map {
+            *entry = reader.read_u16()?;
+        }
+        for color in self.palettes.iter_mut().flatten() {
+            *color = reader.read_u16()?;
+        }
+        Ok(())
+    }
+}
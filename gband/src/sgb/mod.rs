@@ -0,0 +1,754 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use num_enum::TryFromPrimitive;
+
+mod border;
+mod packet;
+
+use border::Border;
+use packet::PacketReceiver;
+
+use crate::ppu::rgb555_to_rgb888;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use crate::{Frame, FRAME_HEIGHT, FRAME_WIDTH};
+
+pub const SGB_FRAME_WIDTH: usize = 256;
+pub const SGB_FRAME_HEIGHT: usize = 224;
+
+pub type SgbFrame = Box<[u8; SGB_FRAME_WIDTH * SGB_FRAME_HEIGHT * 4]>;
+
+/// Position of the Game Boy screen within the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+/// The screen is colored by blocks of 8x8 pixels
+const TILES_X: usize = FRAME_WIDTH / 8;
+const TILES_Y: usize = FRAME_HEIGHT / 8;
+
+/// Bytes read from the screen by the transfer commands, the first 256 tiles shown
+pub const VRAM_TRANSFER_LEN: usize = 0x1000;
+
+/// Palettes sent by PAL_TRN, selected with PAL_SET
+const SYSTEM_PALETTES: usize = 512;
+
+/// Attribute files sent by ATTR_TRN, 2 bits for each tile of the screen
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_LEN: usize = TILES_X * TILES_Y / 4;
+
+/// Colors set by the SGB BIOS before the game sends its own
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+enum Command {
+    Pal01 = 0x00,
+    Pal23 = 0x01,
+    Pal03 = 0x02,
+    Pal12 = 0x03,
+    AttrBlk = 0x04,
+    AttrLin = 0x05,
+    AttrDiv = 0x06,
+    AttrChr = 0x07,
+    PalSet = 0x0A,
+    PalTrn = 0x0B,
+    MltReq = 0x11,
+    ChrTrn = 0x13,
+    PctTrn = 0x14,
+    AttrTrn = 0x15,
+    AttrSet = 0x16,
+    MaskEn = 0x17,
+}
+
+/// What MASK_EN shows instead of the Game Boy screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+enum ScreenMask {
+    Disabled,
+    Freeze,
+    Black,
+    Color0,
+}
+
+/// Where the data copied from the screen by a transfer command goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Palettes,
+    BorderTiles { upper: bool },
+    BorderMap,
+    AttributeFiles,
+}
+
+impl Transfer {
+    fn to_u8(self) -> u8 {
+        match self {
+            Transfer::Palettes => 0,
+            Transfer::BorderTiles { upper: false } => 1,
+            Transfer::BorderTiles { upper: true } => 2,
+            Transfer::BorderMap => 3,
+            Transfer::AttributeFiles => 4,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Transfer::Palettes),
+            1 => Some(Transfer::BorderTiles { upper: false }),
+            2 => Some(Transfer::BorderTiles { upper: true }),
+            3 => Some(Transfer::BorderMap),
+            4 => Some(Transfer::AttributeFiles),
+            _ => None,
+        }
+    }
+}
+
+/// The SNES side of the Super Game Boy, which colors the screen and draws a border around it.
+/// Games control it with packets sent through the joypad register.
+pub struct Sgb {
+    /// The SGB BIOS only listens to games that declare SGB support in their header
+    packets_enabled: bool,
+    receiver: PacketReceiver,
+    /// P14 and P15 as last written
+    joypad_lines: u8,
+
+    /// Colors of the 4 palettes used on screen, in RGB555
+    palettes: [[u16; 4]; 4],
+    /// Palette of each 8x8 block of the screen
+    attributes: [u8; TILES_X * TILES_Y],
+    system_palettes: Vec<u16>,
+    attribute_files: Vec<u8>,
+
+    mask: ScreenMask,
+    /// Screen shown while it is frozen, taken from the first frame after the freeze
+    frozen_shades: Vec<u8>,
+
+    /// Transfers wait for a whole frame to be drawn after the command
+    transfer: Option<(Transfer, u8)>,
+
+    /// Number of joypads enabled by MLT_REQ, and the one currently read
+    players: u8,
+    player: u8,
+
+    border: Border,
+}
+
+impl Sgb {
+    pub fn new(packets_enabled: bool) -> Self {
+        Self {
+            packets_enabled,
+            receiver: Default::default(),
+            joypad_lines: 0x30,
+
+            palettes: [DEFAULT_PALETTE; 4],
+            attributes: [0; TILES_X * TILES_Y],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_LEN],
+
+            mask: ScreenMask::Disabled,
+            frozen_shades: Vec::new(),
+
+            transfer: None,
+
+            players: 1,
+            player: 0,
+
+            border: Default::default(),
+        }
+    }
+
+    /// Go back to the state left by the SGB BIOS
+    pub fn reset(&mut self) {
+        *self = Self::new(self.packets_enabled);
+    }
+
+    /// Watch P14 and P15 for packets and for the end of a multiplayer read
+    pub fn write_joypad(&mut self, data: u8) {
+        let lines = data & 0x30;
+        let previous = core::mem::replace(&mut self.joypad_lines, lines);
+
+        if !self.packets_enabled || lines == previous {
+            return;
+        }
+
+        // The next joypad is selected when P15 goes high
+        if self.players > 1 && previous & 0x20 == 0 && lines & 0x20 != 0 {
+            self.player = (self.player + 1) % self.players;
+        }
+
+        // The lines go back high between pulses
+        if previous == 0x30 {
+            if let Some(command) = self.receiver.pulse(lines) {
+                self.execute(&command);
+            }
+        }
+    }
+
+    /// Joypad read through the joypad register, 0 being the Game Boy's own
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// Whether MLT_REQ enabled more than one joypad.
+    /// The joypad register then holds the current joypad when P14 and P15 are high.
+    pub fn is_multiplayer(&self) -> bool {
+        self.players > 1
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        let command = match Command::try_from(data[0] >> 3) {
+            Ok(command) => command,
+            Err(_) => {
+                log::debug!("Unsupported SGB command: {:02x}", data[0] >> 3);
+                return;
+            }
+        };
+
+        match command {
+            Command::Pal01 => self.set_palette_pair(0, 1, data),
+            Command::Pal23 => self.set_palette_pair(2, 3, data),
+            Command::Pal03 => self.set_palette_pair(0, 3, data),
+            Command::Pal12 => self.set_palette_pair(1, 2, data),
+            Command::AttrBlk => self.attribute_blocks(data),
+            Command::AttrLin => self.attribute_lines(data),
+            Command::AttrDiv => self.attribute_division(data),
+            Command::AttrChr => self.attribute_characters(data),
+            Command::PalSet => {
+                for i in 0..4 {
+                    let number = u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) & 0x1FF;
+                    let start = number as usize * 4;
+                    self.palettes[i].copy_from_slice(&self.system_palettes[start..start + 4]);
+                }
+
+                // The first color is shared by all palettes
+                for i in 1..4 {
+                    self.palettes[i][0] = self.palettes[0][0];
+                }
+
+                if data[9] & 0x80 != 0 {
+                    self.apply_attribute_file(data[9] & 0x3F);
+                }
+                if data[9] & 0x40 != 0 {
+                    self.set_mask(ScreenMask::Disabled);
+                }
+            }
+            Command::PalTrn => self.transfer = Some((Transfer::Palettes, 2)),
+            Command::MltReq => {
+                self.players = match data[1] & 0x03 {
+                    0 => 1,
+                    1 => 2,
+                    _ => 4,
+                };
+                self.player = 0;
+            }
+            Command::ChrTrn => {
+                let upper = data[1] & 0x01 != 0;
+                self.transfer = Some((Transfer::BorderTiles { upper }, 2));
+            }
+            Command::PctTrn => self.transfer = Some((Transfer::BorderMap, 2)),
+            Command::AttrTrn => self.transfer = Some((Transfer::AttributeFiles, 2)),
+            Command::AttrSet => {
+                self.apply_attribute_file(data[1] & 0x3F);
+                if data[1] & 0x40 != 0 {
+                    self.set_mask(ScreenMask::Disabled);
+                }
+            }
+            Command::MaskEn => {
+                let mask = ScreenMask::try_from(data[1] & 0x03).expect("the mask is 2 bits");
+                self.set_mask(mask);
+            }
+        }
+    }
+
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]);
+
+        // The first color is shared by all palettes
+        for palette in &mut self.palettes {
+            palette[0] = color(0);
+        }
+
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    /// ATTR_BLK: color the inside, the border and the outside of rectangles
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        for block in data[2..].chunks_exact(6).take(data[1] as usize) {
+            let mut control = block[0] & 0x07;
+            let inside = block[1] & 0x03;
+            let mut border = (block[1] >> 2) & 0x03;
+            let outside = (block[1] >> 4) & 0x03;
+
+            // Changing only the inside or only the outside changes the border along with it
+            match control {
+                0x01 => {
+                    control |= 0x02;
+                    border = inside;
+                }
+                0x04 => {
+                    control |= 0x02;
+                    border = outside;
+                }
+                _ => {}
+            }
+
+            let (x1, y1) = (block[2] as usize & 0x1F, block[3] as usize & 0x1F);
+            let (x2, y2) = (block[4] as usize & 0x1F, block[5] as usize & 0x1F);
+
+            for y in 0..TILES_Y {
+                for x in 0..TILES_X {
+                    let palette = if x > x1 && x < x2 && y > y1 && y < y2 {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else if (x1..=x2).contains(&x) && (y1..=y2).contains(&y) {
+                        (control & 0x02 != 0).then_some(border)
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+
+                    if let Some(palette) = palette {
+                        self.attributes[y * TILES_X + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    /// ATTR_LIN: color whole rows or columns
+    fn attribute_lines(&mut self, data: &[u8]) {
+        for &line in data[2..].iter().take(data[1] as usize) {
+            let number = line as usize & 0x1F;
+            let palette = (line >> 5) & 0x03;
+
+            if line & 0x80 != 0 {
+                // Horizontal line
+                if number < TILES_Y {
+                    self.attributes[number * TILES_X..(number + 1) * TILES_X].fill(palette);
+                }
+            } else if number < TILES_X {
+                // Vertical line
+                for y in 0..TILES_Y {
+                    self.attributes[y * TILES_X + number] = palette;
+                }
+            }
+        }
+    }
+
+    /// ATTR_DIV: split the screen in two with a line
+    fn attribute_division(&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = (data[1] >> 2) & 0x03;
+        let on_line = (data[1] >> 4) & 0x03;
+        let horizontal = data[1] & 0x40 != 0;
+        let line = data[2] as usize & 0x1F;
+
+        for y in 0..TILES_Y {
+            for x in 0..TILES_X {
+                let position = if horizontal { y } else { x };
+
+                self.attributes[y * TILES_X + x] = match position.cmp(&line) {
+                    core::cmp::Ordering::Less => before,
+                    core::cmp::Ordering::Equal => on_line,
+                    core::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    /// ATTR_CHR: color blocks one by one, starting from a position
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 0x01 != 0;
+
+        if x >= TILES_X || y >= TILES_Y {
+            return;
+        }
+
+        for i in 0..count.min(TILES_X * TILES_Y) {
+            let Some(byte) = data.get(6 + i / 4) else {
+                break;
+            };
+
+            // 4 blocks per byte, starting with the upper bits
+            self.attributes[y * TILES_X + x] = (byte >> (6 - (i % 4) * 2)) & 0x03;
+
+            if vertical {
+                y += 1;
+                if y == TILES_Y {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == TILES_X {
+                    x = 0;
+                    y += 1;
+                }
+            }
+
+            if x >= TILES_X || y >= TILES_Y {
+                break;
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, number: u8) {
+        if number as usize >= ATTRIBUTE_FILES {
+            log::warn!("Invalid SGB attribute file: {number}");
+            return;
+        }
+
+        let file =
+            &self.attribute_files[number as usize * ATTRIBUTE_FILE_LEN..][..ATTRIBUTE_FILE_LEN];
+        for (i, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (file[i / 4] >> (6 - (i % 4) * 2)) & 0x03;
+        }
+    }
+
+    fn set_mask(&mut self, mask: ScreenMask) {
+        self.mask = mask;
+
+        if mask != ScreenMask::Freeze {
+            self.frozen_shades.clear();
+        }
+    }
+
+    /// Color a frame completed by the PPU, using the shade of each of its pixels.
+    /// Pending transfers read their data from that frame.
+    pub fn process_frame(&mut self, frame: &mut Frame, shades: &[u8]) {
+        if let Some((transfer, frames)) = self.transfer {
+            if frames > 1 {
+                self.transfer = Some((transfer, frames - 1));
+            } else {
+                self.transfer = None;
+                self.run_transfer(transfer, &screen_data(shades));
+            }
+        }
+
+        if self.mask == ScreenMask::Freeze && self.frozen_shades.is_empty() {
+            self.frozen_shades = shades.to_vec();
+        }
+
+        let shades = match self.mask {
+            ScreenMask::Freeze => &self.frozen_shades,
+            _ => shades,
+        };
+
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let color = match self.mask {
+                ScreenMask::Black => 0x0000,
+                ScreenMask::Color0 => self.palettes[0][0],
+                ScreenMask::Disabled | ScreenMask::Freeze => {
+                    let tile = (i / FRAME_WIDTH / 8) * TILES_X + (i % FRAME_WIDTH) / 8;
+                    self.palettes[self.attributes[tile] as usize][shades[i] as usize & 0x03]
+                }
+            };
+
+            pixel[..3].copy_from_slice(&rgb555_to_rgb888(color));
+            pixel[3] = 0xFF;
+        }
+    }
+
+    fn run_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        match transfer {
+            Transfer::Palettes => {
+                for (color, bytes) in self.system_palettes.iter_mut().zip(data.chunks_exact(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            Transfer::BorderTiles { upper } => self.border.load_tiles(upper, data),
+            Transfer::BorderMap => self.border.load_map(data),
+            Transfer::AttributeFiles => {
+                let len = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..len]);
+            }
+        }
+    }
+
+    /// Draw the border around a frame colored by `process_frame`
+    pub fn render_border(&self, frame: &Frame) -> SgbFrame {
+        let mut output = allocate_sgb_frame();
+
+        // Transparent pixels of the border show the first color
+        let backdrop = rgb555_to_rgb888(self.palettes[0][0]);
+        for pixel in output.chunks_exact_mut(4) {
+            pixel[..3].copy_from_slice(&backdrop);
+        }
+
+        for (y, line) in frame.chunks_exact(FRAME_WIDTH * 4).enumerate() {
+            let base = ((SCREEN_Y + y) * SGB_FRAME_WIDTH + SCREEN_X) * 4;
+            output[base..base + FRAME_WIDTH * 4].copy_from_slice(line);
+        }
+
+        self.border.draw(output.as_mut_slice());
+        output
+    }
+}
+
+/// Turn the shades shown on screen back into the 2 bits per pixel tiles they were drawn from.
+/// Transfers show the first 256 tiles in order, 20 per line.
+fn screen_data(shades: &[u8]) -> Vec<u8> {
+    let mut data = vec![0; VRAM_TRANSFER_LEN];
+
+    for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+        let tile_x = (tile % TILES_X) * 8;
+        let tile_y = (tile / TILES_X) * 8;
+
+        for row in 0..8 {
+            for column in 0..8 {
+                let shade = shades[(tile_y + row) * FRAME_WIDTH + tile_x + column];
+                bytes[row * 2] |= (shade & 0x01) << (7 - column);
+                bytes[row * 2 + 1] |= ((shade >> 1) & 0x01) << (7 - column);
+            }
+        }
+    }
+
+    data
+}
+
+fn allocate_sgb_frame() -> SgbFrame {
+    // Allocated directly on the heap, as the frame is too big for the stack
+    let data: Vec<u8> = vec![0xFF; SGB_FRAME_WIDTH * SGB_FRAME_HEIGHT * 4];
+    data.into_boxed_slice()
+        .try_into()
+        .expect("the frame has the right size")
+}
+
+impl SaveState for Sgb {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.receiver.save_state(writer);
+        writer.write_u8(self.joypad_lines);
+
+        for color in self.palettes.iter().flatten() {
+            writer.write_u16(*color);
+        }
+        writer.write_bytes(&self.attributes);
+        for color in &self.system_palettes {
+            writer.write_u16(*color);
+        }
+        writer.write_bytes(&self.attribute_files);
+
+        writer.write_u8(self.mask as u8);
+        writer.write_slice(&self.frozen_shades);
+
+        match self.transfer {
+            Some((transfer, frames)) => {
+                writer.write_u8(transfer.to_u8());
+                writer.write_u8(frames);
+            }
+            None => {
+                writer.write_u8(0xFF);
+                writer.write_u8(0);
+            }
+        }
+
+        writer.write_u8(self.players);
+        writer.write_u8(self.player);
+
+        self.border.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.receiver.load_state(reader)?;
+        self.joypad_lines = reader.read_u8()? & 0x30;
+
+        for color in self.palettes.iter_mut().flatten() {
+            *color = reader.read_u16()?;
+        }
+        reader.read_bytes(&mut self.attributes)?;
+        for attribute in &mut self.attributes {
+            *attribute &= 0x03;
+        }
+        for color in &mut self.system_palettes {
+            *color = reader.read_u16()?;
+        }
+        reader.read_bytes(&mut self.attribute_files)?;
+
+        self.mask =
+            ScreenMask::try_from(reader.read_u8()?).map_err(|_| SaveStateError::InvalidData)?;
+        let frozen_len = reader.read_u32()? as usize;
+        if frozen_len != 0 && frozen_len != FRAME_WIDTH * FRAME_HEIGHT {
+            return Err(SaveStateError::InvalidData);
+        }
+        self.frozen_shades.resize(frozen_len, 0);
+        reader.read_bytes(&mut self.frozen_shades)?;
+
+        let transfer = reader.read_u8()?;
+        let frames = reader.read_u8()?;
+        self.transfer = Transfer::from_u8(transfer).map(|transfer| (transfer, frames));
+
+        self.players = match reader.read_u8()? {
+            players @ (1 | 2 | 4) => players,
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        self.player = reader.read_u8()? % self.players;
+
+        self.border.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_packet(sgb: &mut Sgb, packet: &[u8; 16]) {
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+
+        for bit in 0..128 {
+            let one = packet[bit / 8] & (1 << (bit % 8)) != 0;
+            sgb.write_joypad(if one { 0x10 } else { 0x20 });
+            sgb.write_joypad(0x30);
+        }
+
+        // Stop bit
+        sgb.write_joypad(0x20);
+        sgb.write_joypad(0x30);
+    }
+
+    fn new_frame() -> Frame {
+        vec![0u8; FRAME_WIDTH * FRAME_HEIGHT * 4]
+            .into_boxed_slice()
+            .try_into()
+            .unwrap()
+    }
+
+    /// What the screen shows when the game sets up a transfer of `data`
+    fn screen_shades(data: &[u8]) -> Vec<u8> {
+        let mut shades = vec![0u8; FRAME_WIDTH * FRAME_HEIGHT];
+
+        for (tile, bytes) in data.chunks_exact(16).enumerate() {
+            for row in 0..8 {
+                for column in 0..8 {
+                    let lo = (bytes[row * 2] >> (7 - column)) & 0x01;
+                    let hi = (bytes[row * 2 + 1] >> (7 - column)) & 0x01;
+                    let y = (tile / TILES_X) * 8 + row;
+                    let x = (tile % TILES_X) * 8 + column;
+                    shades[y * FRAME_WIDTH + x] = (hi << 1) | lo;
+                }
+            }
+        }
+
+        shades
+    }
+
+    fn pixel(frame: &Frame, x: usize, y: usize) -> [u8; 3] {
+        let base = (y * FRAME_WIDTH + x) * 4;
+        frame[base..base + 3].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_palettes_and_attributes() {
+        let mut sgb = Sgb::new(true);
+        let mut frame = new_frame();
+        let shades = vec![3u8; FRAME_WIDTH * FRAME_HEIGHT];
+
+        // PAL01: palette 0 ends with red, palette 1 with blue
+        let mut packet = [0u8; 16];
+        packet[0] = 0x01;
+        packet[1..3].copy_from_slice(&0x7FFFu16.to_le_bytes());
+        packet[7..9].copy_from_slice(&0x001Fu16.to_le_bytes());
+        packet[13..15].copy_from_slice(&0x7C00u16.to_le_bytes());
+        send_packet(&mut sgb, &packet);
+
+        // ATTR_DIV: palette 1 from column 10, on the right
+        let mut packet = [0u8; 16];
+        packet[0] = (0x06 << 3) | 1;
+        packet[1] = 0x01 | (0x01 << 4);
+        packet[2] = 10;
+        send_packet(&mut sgb, &packet);
+
+        sgb.process_frame(&mut frame, &shades);
+        assert_eq!(pixel(&frame, 0, 0), [0xFF, 0x00, 0x00]);
+        assert_eq!(pixel(&frame, 80, 0), [0x00, 0x00, 0xFF]);
+
+        // ATTR_LIN: the first row uses palette 0 again
+        let mut packet = [0u8; 16];
+        packet[0] = (0x05 << 3) | 1;
+        packet[1] = 1;
+        packet[2] = 0x80;
+        send_packet(&mut sgb, &packet);
+
+        // MASK_EN: show the first color instead of the screen
+        let mut packet = [0u8; 16];
+        packet[0] = (0x17 << 3) | 1;
+        packet[1] = 3;
+        send_packet(&mut sgb, &packet);
+
+        sgb.process_frame(&mut frame, &shades);
+        assert_eq!(pixel(&frame, 80, 0), [0xFF, 0xFF, 0xFF]);
+
+        packet[1] = 0;
+        send_packet(&mut sgb, &packet);
+
+        sgb.process_frame(&mut frame, &shades);
+        assert_eq!(pixel(&frame, 80, 0), [0xFF, 0x00, 0x00]);
+        assert_eq!(pixel(&frame, 80, 8), [0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_border_transfer() {
+        let mut sgb = Sgb::new(true);
+        let mut frame = new_frame();
+
+        // CHR_TRN, with every pixel of the screen using shade 1.
+        // Border tiles are made of 2 Game Boy tiles, so every border pixel uses color 5.
+        let mut packet = [0u8; 16];
+        packet[0] = (0x13 << 3) | 1;
+        send_packet(&mut sgb, &packet);
+
+        let shades = vec![1u8; FRAME_WIDTH * FRAME_HEIGHT];
+        sgb.process_frame(&mut frame, &shades);
+        sgb.process_frame(&mut frame, &shades);
+
+        // PCT_TRN, with a map of tile 0 using the first palette, where color 5 is green
+        let mut packet = [0u8; 16];
+        packet[0] = (0x14 << 3) | 1;
+        send_packet(&mut sgb, &packet);
+
+        let mut data = vec![0u8; VRAM_TRANSFER_LEN];
+        data[0x80A..0x80C].copy_from_slice(&0x03E0u16.to_le_bytes());
+        let shades = screen_shades(&data);
+        assert_eq!(screen_data(&shades), data);
+
+        sgb.process_frame(&mut frame, &shades);
+        sgb.process_frame(&mut frame, &shades);
+
+        let output = sgb.render_border(&frame);
+        let base = (10 * SGB_FRAME_WIDTH + 10) * 4;
+        assert_eq!(&output[base..base + 3], &[0x00, 0xFF, 0x00]);
+
+        // The border is drawn over the screen
+        let base = (SCREEN_Y * SGB_FRAME_WIDTH + SCREEN_X) * 4;
+        assert_eq!(&output[base..base + 3], &[0x00, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let mut sgb = Sgb::new(true);
+
+        // MLT_REQ: 2 players
+        let mut packet = [0u8; 16];
+        packet[0] = (0x11 << 3) | 1;
+        packet[1] = 1;
+        send_packet(&mut sgb, &packet);
+        assert!(sgb.is_multiplayer());
+        assert_eq!(sgb.player(), 0);
+
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        assert_eq!(sgb.player(), 1);
+
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        assert_eq!(sgb.player(), 0);
+
+        // Games without SGB support can't send commands
+        let mut sgb = Sgb::new(false);
+        send_packet(&mut sgb, &packet);
+        assert!(!sgb.is_multiplayer());
+    }
+}
@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Bytes in a single packet
+pub const PACKET_LEN: usize = 16;
+
+/// A command can span up to 7 packets
+const MAX_PACKETS: usize = 7;
+
+/// Decodes the packets sent one bit at a time by pulsing P14 and P15 of the joypad register.
+/// A reset pulse starts a packet, then each pulse on P14 sends a 0 and each pulse on P15 sends a 1.
+#[derive(Default)]
+pub struct PacketReceiver {
+    /// Bits received in the current packet, None while waiting for a reset pulse
+    bit: Option<u8>,
+    packet: [u8; PACKET_LEN],
+
+    /// Packets of the command being received
+    command: Vec<u8>,
+}
+
+impl PacketReceiver {
+    /// Handle a pulse on the lines, which are both high between pulses.
+    /// Returns the command once all of its packets are received.
+    pub fn pulse(&mut self, lines: u8) -> Option<Vec<u8>> {
+        match (lines, self.bit) {
+            (0x00, _) => {
+                // Reset pulse
+                self.bit = Some(0);
+                self.packet = [0; PACKET_LEN];
+                None
+            }
+            (0x10 | 0x20, Some(bit)) if bit as usize == PACKET_LEN * 8 => {
+                // Stop bit, which must be a 0
+                self.bit = None;
+
+                if lines == 0x20 {
+                    self.finish_packet()
+                } else {
+                    log::warn!("Invalid stop bit in SGB packet");
+                    self.command.clear();
+                    None
+                }
+            }
+            (0x10 | 0x20, Some(bit)) => {
+                // Bits are sent starting with the least significant
+                if lines == 0x10 {
+                    self.packet[bit as usize / 8] |= 1 << (bit % 8);
+                }
+
+                self.bit = Some(bit + 1);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn finish_packet(&mut self) -> Option<Vec<u8>> {
+        self.command.extend_from_slice(&self.packet);
+
+        // The first byte holds the number of packets of the command
+        let packets = (self.command[0] as usize & 0x07).max(1);
+        if self.command.len() >= packets * PACKET_LEN {
+            Some(core::mem::take(&mut self.command))
+        } else {
+            None
+        }
+    }
+}
+
+impl SaveState for PacketReceiver {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bit.unwrap_or(0xFF));
+        writer.write_bytes(&self.packet);
+        writer.write_slice(&self.command);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.bit = match reader.read_u8()? {
+            0xFF => None,
+            bit if bit as usize <= PACKET_LEN * 8 => Some(bit),
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        reader.read_bytes(&mut self.packet)?;
+
+        let len = reader.read_u32()? as usize;
+        if len > PACKET_LEN * MAX_PACKETS || !len.is_multiple_of(PACKET_LEN) {
+            return Err(SaveStateError::InvalidData);
+        }
+        self.command.resize(len, 0);
+        reader.read_bytes(&mut self.command)
+    }
+}
@@ -0,0 +1,48 @@
+use alloc::boxed::Box;
+
+/// Provides the current time to the clocks on cartridges
+pub trait TimeSource: Sync + Send {
+    /// Current time, in seconds since the UNIX epoch.
+    /// `emulated_seconds` is how long the emulator has been running, for sources following the emulation.
+    fn now(&self, emulated_seconds: u64) -> u64;
+}
+
+/// Deterministic time, which only advances with the emulation
+pub struct EmulatedTime {
+    start: u64,
+}
+
+impl EmulatedTime {
+    /// `start` is the time when the emulator started, in seconds since the UNIX epoch
+    pub fn new(start: u64) -> Self {
+        Self { start }
+    }
+}
+
+impl TimeSource for EmulatedTime {
+    fn now(&self, emulated_seconds: u64) -> u64 {
+        self.start + emulated_seconds
+    }
+}
+
+/// Shifts the time of another source, to set the in-game clock.
+/// The offset isn't kept in save files, so it has to be given again on every launch.
+pub struct OffsetTime {
+    inner: Box<dyn TimeSource>,
+    offset: i64,
+}
+
+impl OffsetTime {
+    /// `offset` is added to the time of `inner`, in seconds
+    pub fn new(inner: Box<dyn TimeSource>, offset: i64) -> Self {
+        Self { inner, offset }
+    }
+}
+
+impl TimeSource for OffsetTime {
+    fn now(&self, emulated_seconds: u64) -> u64 {
+        self.inner
+            .now(emulated_seconds)
+            .saturating_add_signed(self.offset)
+    }
+}
@@ -1,5 +1,7 @@
 use bitflags::bitflags;
 
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Default)]
 pub struct TimerRegisters {
     div: u16,
@@ -87,6 +89,26 @@ impl TimerRegisters {
         }
     }
 
+    /// Number of CPU cycles before the timer does anything else than incrementing DIV
+    pub fn idle_cycles(&self) -> u32 {
+        if self.interrupt_cycle_countdown != 0 {
+            0
+        } else if self.control.contains(TimerControl::ENABLED) {
+            cycles_until_falling_edge(self.div, self.control.get_mask())
+        } else {
+            u32::MAX
+        }
+    }
+
+    /// Skip cycles returned by `idle_cycles`
+    pub fn skip(&mut self, cycles: u32) {
+        self.set_div(self.div.wrapping_add((cycles as u16).wrapping_mul(4)));
+    }
+
+    pub fn get_div(&self) -> u16 {
+        self.div
+    }
+
     pub fn reset_div(&mut self) {
         self.set_div(0);
     }
@@ -116,6 +138,37 @@ impl TimerRegisters {
     }
 }
 
+/// Number of CPU cycles before the bit of DIV selected by `mask` falls, not counting the cycle where it does.
+/// DIV is incremented by 4 every CPU cycle.
+pub fn cycles_until_falling_edge(div: u16, mask: u16) -> u32 {
+    // The bit falls every time DIV wraps around a multiple of twice the bit
+    let period = mask as u32 * 2;
+    let remaining = period - (div as u32 % period);
+
+    remaining.div_ceil(4) - 1
+}
+
+impl SaveState for TimerRegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.div);
+        writer.write_u8(self.counter);
+        writer.write_u8(self.modulo);
+        writer.write_u8(self.control.bits());
+        writer.write_bool(self.tac_falling_edge_latch);
+        writer.write_u8(self.interrupt_cycle_countdown);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.div = reader.read_u16()?;
+        self.counter = reader.read_u8()?;
+        self.modulo = reader.read_u8()?;
+        self.control = TimerControl::from_bits_truncate(reader.read_u8()?);
+        self.tac_falling_edge_latch = reader.read_bool()?;
+        self.interrupt_cycle_countdown = reader.read_u8()?;
+        Ok(())
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct TimerControl: u8 {
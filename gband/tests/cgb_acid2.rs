@@ -13,18 +13,10 @@ fn cgb_acid2() {
 
     // Skip a few frames
     for _ in 0..16 {
-        loop {
-            if let Some(_) = emulator.clock() {
-                break;
-            }
-        }
+        emulator.run_frame();
     }
 
-    let frame = loop {
-        if let Some(f) = emulator.clock() {
-            break f;
-        }
-    };
+    let frame = emulator.run_frame();
 
     assert_eq!(frame.as_slice(), &cgb_acid2_image);
 }
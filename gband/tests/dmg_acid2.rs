@@ -13,18 +13,10 @@ fn dmg_acid2() {
 
     // Skip a few frames
     for _ in 0..10 {
-        loop {
-            if let Some(_) = emulator.clock() {
-                break;
-            }
-        }
+        emulator.run_frame();
     }
 
-    let frame = loop {
-        if let Some(f) = emulator.clock() {
-            break f;
-        }
-    };
+    let frame = emulator.run_frame();
 
     assert_eq!(frame.as_slice(), &dmg_acid2_image);
 }
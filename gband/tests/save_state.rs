@@ -0,0 +1,103 @@
+use gband::{Emulator, EmulatorOptions, HardwareModel, SaveStateError};
+
+/// Builds a small MBC1 ROM that keeps incrementing a byte in cartridge RAM
+/// and mirrors it in VRAM and HRAM.
+fn build_rom(title: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+
+    // Entry point: nop; jp 0x0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    rom[0x147] = 0x03; // MBC1 + RAM + Battery
+    rom[0x149] = 0x02; // 8 KiB RAM
+
+    let mut checksum = 0u8;
+    for b in &rom[0x134..0x14D] {
+        checksum = checksum.wrapping_sub(*b).wrapping_sub(1);
+    }
+    rom[0x14D] = checksum;
+
+    rom[0x150..0x161].copy_from_slice(&[
+        0x3E, 0x0A, // ld a, 0x0A
+        0xEA, 0x00, 0x00, // ld (0x0000), a
+        0x21, 0x00, 0xA0, // ld hl, 0xA000
+        0x34, // inc (hl)
+        0x7E, // ld a, (hl)
+        0xEA, 0x00, 0x98, // ld (0x9800), a
+        0xE0, 0x80, // ldh (0x80), a
+        0x18, 0xF7, // jr -9
+    ]);
+
+    rom
+}
+
+#[test]
+fn save_state_roundtrip() {
+    let rom = build_rom(b"SAVESTATE");
+    let mut emulator = Emulator::new(&rom, None).expect("Invalid Rom!");
+
+    emulator.run_cycles(100_000);
+    let state = emulator.save_state();
+
+    emulator.run_cycles(50_000);
+    let expected = emulator.save_state();
+
+    emulator.load_state(&state).expect("state should load");
+    assert_eq!(emulator.save_state(), state);
+
+    emulator.run_cycles(50_000);
+    assert_eq!(emulator.save_state(), expected);
+}
+
+#[test]
+fn save_state_rejects_invalid_data() {
+    let rom = build_rom(b"SAVESTATE");
+    let mut emulator = Emulator::new(&rom, None).expect("Invalid Rom!");
+    emulator.run_cycles(10_000);
+
+    let state = emulator.save_state();
+
+    let mut bad_magic = state.clone();
+    bad_magic[0] ^= 0xFF;
+    assert!(matches!(
+        emulator.load_state(&bad_magic),
+        Err(SaveStateError::InvalidMagic)
+    ));
+
+    let mut bad_version = state.clone();
+    bad_version[4..6].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    assert!(matches!(
+        emulator.load_state(&bad_version),
+        Err(SaveStateError::UnsupportedVersion(0xFFFF))
+    ));
+
+    // A truncated state must leave the emulator untouched
+    emulator.run_cycles(10_000);
+    let before = emulator.save_state();
+    assert!(matches!(
+        emulator.load_state(&state[..state.len() - 1]),
+        Err(SaveStateError::UnexpectedEof)
+    ));
+    assert_eq!(emulator.save_state(), before);
+
+    // A state from another game is rejected
+    let other_rom = build_rom(b"OTHERGAME");
+    let mut other = Emulator::new(&other_rom, None).expect("Invalid Rom!");
+    assert!(matches!(
+        other.load_state(&state),
+        Err(SaveStateError::RomMismatch)
+    ));
+
+    // A state saved without the Super Game Boy can't be loaded with it
+    let options = |model| EmulatorOptions {
+        model: Some(model),
+        ..Default::default()
+    };
+    let dmg = Emulator::with_options(&rom, None, options(HardwareModel::Dmg)).unwrap();
+    let mut sgb = Emulator::with_options(&rom, None, options(HardwareModel::Sgb)).unwrap();
+    assert!(matches!(
+        sgb.load_state(&dmg.save_state()),
+        Err(SaveStateError::SgbMismatch)
+    ));
+}
@@ -0,0 +1,104 @@
+use gband::Emulator;
+
+/// Builds a ROM that spends most of its time halted, or in a busy loop when `halt` is false,
+/// interrupted by VBlank and the timer while a sound plays
+fn build_rom(lcd_control: u8, halt: bool) -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+
+    // VBlank: inc c; reti
+    rom[0x40..0x42].copy_from_slice(&[0x0C, 0xD9]);
+    // Timer: inc d; reti
+    rom[0x50..0x52].copy_from_slice(&[0x14, 0xD9]);
+
+    // Entry point: nop; jp 0x0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+
+    rom[0x134..0x13D].copy_from_slice(b"SCHEDULER");
+
+    let mut checksum = 0u8;
+    for b in &rom[0x134..0x14D] {
+        checksum = checksum.wrapping_sub(*b).wrapping_sub(1);
+    }
+    rom[0x14D] = checksum;
+
+    let wait = if halt { 0x76 } else { 0x00 };
+    rom[0x150..0x175].copy_from_slice(&[
+        0x3E,
+        lcd_control, // ld a, lcd_control
+        0xE0,
+        0x40, // ldh (LCDC), a
+        0x3E,
+        0xF0, // ld a, 0xF0
+        0xE0,
+        0x17, // ldh (NR22), a
+        0x3E,
+        0x87, // ld a, 0x87
+        0xE0,
+        0x19, // ldh (NR24), a
+        0x3E,
+        0x80, // ld a, 0x80
+        0xE0,
+        0x06, // ldh (TMA), a
+        0x3E,
+        0x05, // ld a, 0x05
+        0xE0,
+        0x07, // ldh (TAC), a
+        0x3E,
+        0xC0, // ld a, 0xC0
+        0xE0,
+        0x46, // ldh (DMA), a
+        0x3E,
+        0x05, // ld a, VBLANK | TIMER
+        0xE0,
+        0xFF, // ldh (IE), a
+        0xFB, // ei
+        wait, // halt, or nop for a busy loop
+        0x04, // inc b
+        0x78, // ld a, b
+        0xEA,
+        0x00,
+        0x98, // ld (0x9800), a
+        0x18,
+        0xF8, // jr -8
+    ]);
+
+    rom
+}
+
+#[test]
+fn scheduler_matches_clock() {
+    for (lcd_control, halt) in [(0x91, true), (0x00, true), (0x91, false), (0x00, false)] {
+        let rom = build_rom(lcd_control, halt);
+
+        let mut clocked = Emulator::new(&rom, None).expect("Invalid Rom!");
+        let mut scheduled = Emulator::new(&rom, None).expect("Invalid Rom!");
+        clocked.set_audio_sample_rate(48000);
+        scheduled.set_audio_sample_rate(48000);
+
+        for _ in 0..5 {
+            let expected_frame = loop {
+                if let Some(frame) = clocked.clock() {
+                    break frame;
+                }
+            };
+
+            let frame = scheduled.run_frame();
+            assert_eq!(frame.as_slice(), expected_frame.as_slice());
+            assert_eq!(scheduled.save_state(), clocked.save_state());
+            assert_eq!(
+                scheduled.drain_audio_samples(),
+                clocked.drain_audio_samples()
+            );
+        }
+
+        // Stop anywhere, including in the middle of idle stretches
+        for cycles in 1..500 {
+            for _ in 0..cycles * 7 {
+                clocked.clock();
+            }
+
+            scheduled.run_cycles(cycles * 7);
+            assert_eq!(scheduled.save_state(), clocked.save_state());
+        }
+    }
+}